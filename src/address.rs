@@ -1,14 +1,22 @@
 use std::fmt::{Debug, Display, Formatter};
 use std::str::FromStr;
 use thiserror::Error;
+use crate::assignment::parse_integer;
 use crate::program_error::ParseError;
 use crate::register::Register;
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Address {
     Register(Register),
     StackPointer(usize),
-    Reference(Destination)
+    /// Index into the heap, which is allocated with `syscall malloc`
+    HeapPointer(usize),
+    Reference(Destination),
+    /// The index of the currently executed instruction. Only writable with `mov`
+    ProgramCounter,
+    /// Name of a read-only string defined with `.string name "value"`
+    Symbol(String),
 }
 
 
@@ -17,11 +25,41 @@ impl Display for Address {
         write!(f, "{}", match self {
             Address::Register(register) => format!("{register}"),
             Address::StackPointer(stack_pointer) => format!("0x{stack_pointer}"),
+            Address::HeapPointer(heap_pointer) => format!("hp[{heap_pointer}]"),
             Address::Reference(destination) => format!("{}", *destination),
+            Address::ProgramCounter => "pc".to_string(),
+            Address::Symbol(name) => name.to_string(),
         })
     }
 }
 
+impl Address {
+    /// Returns the address in the syntax it is written in the source code
+    pub fn disassemble(&self) -> String {
+        match self {
+            Address::Register(register) => format!("{register}"),
+            Address::StackPointer(stack_pointer) => format!("sp[{stack_pointer}]"),
+            Address::HeapPointer(heap_pointer) => format!("hp[{heap_pointer}]"),
+            Address::Reference(Destination::Register(register)) => format!("[{register}]"),
+            Address::Reference(Destination::StackPointer(stack_pointer)) => format!("[sp[{stack_pointer}]]"),
+            Address::ProgramCounter => "pc".to_string(),
+            Address::Symbol(name) => name.to_string(),
+        }
+    }
+
+    /// Name of the kind of the address, e.g. `register`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Address::Register(_) => "register",
+            Address::StackPointer(_) => "stack",
+            Address::HeapPointer(_) => "heap",
+            Address::Reference(_) => "reference",
+            Address::ProgramCounter => "program counter",
+            Address::Symbol(_) => "symbol",
+        }
+    }
+}
+
 pub trait TryAdd<T> {
     type Output;
     type Error;
@@ -31,12 +69,23 @@ pub trait TryAdd<T> {
 #[derive(Debug, Error, Clone)]
 pub enum TryOperateTypes {
     IncompatibleTypes(String, String),
+    /// The address moved by the offset lies below 0
+    Underflow(String, isize),
+    /// Only two stack addresses can be added
+    IncompatibleAddresses(Address, Address),
 }
 
 impl Display for TryOperateTypes {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", match self {
-            TryOperateTypes::IncompatibleTypes(a, b) => format!("({a}, {b})")
+            TryOperateTypes::IncompatibleTypes(a, b) => format!("({a}, {b})"),
+            TryOperateTypes::Underflow(a, offset) => format!("{a} moved by {offset} lies below 0"),
+            TryOperateTypes::IncompatibleAddresses(a1, a2) if a1.kind() == a2.kind() => {
+                format!("Cannot add two {} addresses: {} + {}", a1.kind(), a1.disassemble(), a2.disassemble())
+            },
+            TryOperateTypes::IncompatibleAddresses(a1, a2) => {
+                format!("Cannot add a {} address and a {} address: {} + {}", a1.kind(), a2.kind(), a1.disassemble(), a2.disassemble())
+            }
         })
     }
 }
@@ -48,7 +97,7 @@ impl TryAdd<Address> for Address {
     fn try_add(&self, rhs: &Address) -> Result<Self::Output, Self::Error>  {
         match (&self, &rhs) {
             (Address::StackPointer(i), Address::StackPointer(j)) => Ok(Address::StackPointer(*i + *j)),
-            (a1, a2) => Err(TryOperateTypes::IncompatibleTypes((*a1).clone().to_string(), (*a2).clone().to_string()))
+            (a1, a2) => Err(TryOperateTypes::IncompatibleAddresses((*a1).clone(), (*a2).clone()))
         }
     }
 }
@@ -59,7 +108,8 @@ impl TryAdd<isize> for Address {
 
     fn try_add(&self, rhs: &isize) -> Result<Self::Output, Self::Error>  {
         match (&self, rhs) {
-            (Address::StackPointer(i), j) => Ok(Address::StackPointer((((*i) as isize) + *j) as usize)),
+            (Address::StackPointer(i), j) => Ok(Address::StackPointer(i.checked_add_signed(*j).ok_or_else(|| TryOperateTypes::Underflow(self.disassemble(), *j))?)),
+            (Address::HeapPointer(i), j) => Ok(Address::HeapPointer(i.checked_add_signed(*j).ok_or_else(|| TryOperateTypes::Underflow(self.disassemble(), *j))?)),
             (a1, a2) => Err(TryOperateTypes::IncompatibleTypes((*a1).to_string(), (*a2).to_string()))
         }
     }
@@ -79,24 +129,47 @@ impl FromStr for Address {
             return match address {
                 Address::Reference(reference) => Ok(Address::Reference(reference)),
                 Address::Register(register) => Ok(Address::Reference(Destination::Register(register))),
-                Address::StackPointer(s) => Ok(Address::Reference(Destination::StackPointer(s)))
+                Address::StackPointer(s) => Ok(Address::Reference(Destination::StackPointer(s))),
+                Address::HeapPointer(_) => Err(ParseError::new("Heap addresses cannot be dereferenced directly, load them into a register first")),
+                Address::ProgramCounter => Err(ParseError::new("The program counter cannot be dereferenced")),
+                Address::Symbol(name) => Err(ParseError::new(&format!("The symbol {name} cannot be dereferenced")))
             }
         }
 
         if let ["sp", "[", index, "]"] = &s.replace('[', " [ ").replace(']', " ] ").split_whitespace().collect::<Vec<_>>()[..] {
-            return Ok(Address::StackPointer(index.parse::<usize>()?));
+            return Ok(Address::StackPointer(parse_index(index)?));
+        }
+
+        if let ["hp", "[", index, "]"] = &s.replace('[', " [ ").replace(']', " ] ").split_whitespace().collect::<Vec<_>>()[..] {
+            return Ok(Address::HeapPointer(parse_index(index)?));
         }
 
-        match s {
+        match s.trim() {
             "rax" => Ok(Address::Register(Register::Rax)),
             "rbx" => Ok(Address::Register(Register::Rbx)),
             "rcx" => Ok(Address::Register(Register::Rcx)),
+            "rdx" => Ok(Address::Register(Register::Rdx)),
+            "pc" => Ok(Address::ProgramCounter),
+            symbol if is_symbol(symbol) => Ok(Address::Symbol(symbol.to_string())),
             a => Err(ParseError::new(&format!("Address unknown: {a}")))
         }
     }
 }
 
+/// Parses a stack or heap index, which can be written in hexadecimal as well, e.g. `sp[0x10]`
+fn parse_index(index: &str) -> Result<usize, ParseError> {
+    usize::try_from(parse_integer(index)?).map_err(|_| ParseError::new(&format!("Index {index} must not be negative")))
+}
+
+/// Symbols start with a letter or an underscore, followed by letters, digits or underscores
+pub fn is_symbol(s: &str) -> bool {
+    let mut chars = s.chars();
+
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_') && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Destination {
     Register(Register),
     StackPointer(usize)
@@ -110,3 +183,42 @@ impl Display for Destination {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn whitespace_inside_brackets_is_ignored() {
+        for (spaced, unspaced) in [("[ rax ]", "[rax]"), ("sp[ 3 ]", "sp[3]"), ("sp [ 3 ]", "sp[3]"), ("[ sp [ 3 ] ]", "[sp[3]]"), (" rax ", "rax")] {
+            assert_eq!(Address::from_str(spaced).unwrap(), Address::from_str(unspaced).unwrap(), "{spaced:?}");
+        }
+    }
+
+    #[test]
+    fn spaced_references_parse_in_commands() {
+        use crate::command::Command;
+
+        assert_eq!(Command::from_str("mov [ rax ] 5").unwrap(), Command::from_str("mov [rax] 5").unwrap());
+        assert_eq!(Command::from_str("mov [ sp [ 3 ] ] \"[ x ]\"").unwrap().to_string(), "mov [sp[3]] \"[ x ]\"");
+    }
+
+    #[test]
+    fn only_stack_addresses_can_be_added() {
+        assert_eq!(Address::StackPointer(2).try_add(&Address::StackPointer(3)).unwrap(), Address::StackPointer(5));
+
+        let error = Address::Register(Register::Rax).try_add(&Address::Register(Register::Rbx)).unwrap_err();
+        assert_eq!(error.to_string(), "Cannot add two register addresses: rax + rbx");
+
+        let error = Address::StackPointer(1).try_add(&Address::HeapPointer(2)).unwrap_err();
+        assert_eq!(error.to_string(), "Cannot add a stack address and a heap address: sp[1] + hp[2]");
+    }
+
+    #[test]
+    fn indices_can_be_hexadecimal() {
+        assert_eq!(Address::from_str("sp[0x10]").unwrap(), Address::StackPointer(16));
+        assert_eq!(Address::from_str("hp[0xa]").unwrap(), Address::HeapPointer(10));
+        assert!(Address::from_str("sp[-0x1]").unwrap_err().to_string().contains("must not be negative"));
+        assert!(Address::from_str("sp[0xZZ]").is_err());
+    }
+}
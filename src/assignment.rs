@@ -1,11 +1,14 @@
 use std::cmp::Ordering;
 use std::fmt::{Debug, Display, Formatter};
+use std::num::{IntErrorKind, ParseIntError};
 use std::str::FromStr;
 use thiserror::Error;
 use crate::address::{Address, Destination, TryAdd, TryOperateTypes};
+use crate::memory::STRING_LIMIT;
 use crate::program_error::ParseError;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Assignment {
     Value(Type),
     Address(Address),
@@ -15,7 +18,7 @@ pub enum Assignment {
 impl From<Destination> for Assignment {
     fn from(destination: Destination) -> Self {
         match destination {
-            Destination::Register(register) => Assignment::Address(Address::Register(register.clone())),
+            Destination::Register(register) => Assignment::Address(Address::Register(register)),
             Destination::StackPointer(s) => Assignment::Address(Address::StackPointer(s)),
         }
     }
@@ -24,9 +27,14 @@ impl From<Destination> for Assignment {
 #[derive(Debug, Error, Clone)]
 pub enum OperationError {
     Subtraction(Type, Type),
+    Multiplication(Type, Type),
+    Division(Type, Type),
+    Remainder(Type, Type),
+    DivideByZero,
     TryAdd(#[from] TryOperateTypes),
     TryCmp(TryOperateTypes),
-    WrongType { expected: String, actual: String }
+    WrongType { expected: String, actual: String },
+    StringTooLong { length: usize, limit: usize }
 }
 
 impl Display for OperationError {
@@ -35,20 +43,51 @@ impl Display for OperationError {
             OperationError::Subtraction(t1, t2) => {
                 format!("Attempted subtracting two incompatible types: [{t1}] - [{t2}]")
             }
+            OperationError::Multiplication(t1, t2) => {
+                format!("Attempted multiplying two incompatible types: [{t1}] * [{t2}]")
+            }
+            OperationError::Division(t1, t2) => {
+                format!("Attempted dividing two incompatible types: [{t1}] / [{t2}]")
+            }
+            OperationError::Remainder(t1, t2) => {
+                format!("Attempted computing the remainder of two incompatible types: [{t1}] % [{t2}]")
+            }
+            OperationError::DivideByZero => "Attempted dividing by zero".to_string(),
+            OperationError::TryAdd(a @ TryOperateTypes::Underflow(_, _)) => format!("Address out of bounds: {a}"),
+            OperationError::TryAdd(a @ TryOperateTypes::IncompatibleAddresses(_, _)) => format!("{a}"),
             OperationError::TryAdd(a) => format!("Attempting adding two incompatible types: {a}"),
             OperationError::TryCmp(a) => format!("Attempting comparing two incompatible types: {a}"),
             OperationError::WrongType { expected, actual } => {
                 format!("Type {expected} is expected but the actual value was {actual}")
             }
+            OperationError::StringTooLong { length, limit } => {
+                format!("Cannot create a string of {length} bytes, the limit is {limit} bytes")
+            }
         })
     }
 }
 
+/// Names of all types, as returned by `Type::type_name`
+pub const TYPE_NAMES: &[&str] = &["Untyped", "Integer", "String", "Address", "Char", "Array", "Float", "Boolean"];
+
 #[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Type {
+    /// Written as `"text"`, with the same escape sequences as characters, e.g. `"line\nbreak"`
     String(String),
     Integer(isize),
+    /// A floating point number, written with a `.` or an exponent, e.g. `3.5`, `1e3` or `2.5e-2`, as well as `inf`,
+    /// `-inf` and `nan`. Arithmetic with integers results in a float. `nan` isn't ordered, so comparing it with `cmp` fails
+    Float(f64),
+    /// `true` or `false`. Conditional jumps treat `true` like an equal comparison, so `je` branches on `true`
+    Boolean(bool),
+    /// A single character, written as `'A'`. The escape sequences `\n`, `\t`, `\r`, `\0`, `\'`, `\"` and `\\` are supported.
+    /// Arithmetic with integers uses its code point and results in an integer,
+    /// so `'A' + 1` is `66`
+    Char(char),
     Address(Address),
+    /// A fixed-size array, created with `arrnew` and accessed with `arrget` and `arrset`
+    Array(Vec<Type>),
     Untyped
 }
 
@@ -57,7 +96,11 @@ impl Debug for Type {
         write!(f, "{}", match self {
             Type::String(a) => format!("{a:?}"),
             Type::Integer(a) => format!("{a}"),
+            Type::Float(a) => format!("{a:?}"),
+            Type::Boolean(a) => format!("{a}"),
+            Type::Char(a) => format!("{a:?}"),
             Type::Address(a) => format!("[{a}]"),
+            Type::Array(a) => format!("{a:?}"),
             Type::Untyped => "Untyped".to_string(),
         })
     }
@@ -66,30 +109,175 @@ impl Debug for Type {
 impl Display for Type {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", match self {
-            Type::String(a) => format!("String '{a}'"),
+            Type::String(a) => format!("String '{}'", escape_control(a)),
             Type::Integer(a) => format!("Integer '{a}'"),
+            Type::Float(a) => format!("Float '{a:?}'"),
+            Type::Boolean(a) => format!("Boolean '{a}'"),
+            Type::Char(a) => format!("Char '{}'", escape_control(&a.to_string())),
             Type::Address(a) => format!("Address '[{a}]'"),
+            Type::Array(a) => format!("Array {a:?}"),
             Type::Untyped => "Untyped".to_string(),
         })
     }
 }
 
+/// Base in which integers are displayed
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Radix {
+    #[default]
+    Decimal,
+    Hexadecimal,
+    Binary,
+}
+
+impl Radix {
+    pub fn format(&self, value: isize) -> String {
+        let sign = if value < 0 { "-" } else { "" };
+
+        match self {
+            Radix::Decimal => value.to_string(),
+            Radix::Hexadecimal => format!("{sign}0x{:x}", value.unsigned_abs()),
+            Radix::Binary => format!("{sign}0b{:b}", value.unsigned_abs()),
+        }
+    }
+}
+
+/// Displays a type like its `Debug` and `Display` implementation, but with integers in the radix
+pub struct RadixDisplay<'a>(&'a Type, Radix);
+
+impl Debug for RadixDisplay<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Type::Integer(a) => write!(f, "{}", self.1.format(*a)),
+            rest => write!(f, "{rest:?}")
+        }
+    }
+}
+
+impl Display for RadixDisplay<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Type::Integer(a) => write!(f, "Integer '{}'", self.1.format(*a)),
+            rest => write!(f, "{rest}")
+        }
+    }
+}
+
 impl Type {
+    pub fn with_radix(&self, radix: Radix) -> RadixDisplay<'_> {
+        RadixDisplay(self, radix)
+    }
+
+    /// Subtracting a string from a string removes its first occurrence. If it doesn't occur,
+    /// the string is returned unchanged
     pub fn sub(&self, other: &Type) -> Result<Type, OperationError> {
+        reject_code_addresses(self, other)?;
+
         if let (Type::Integer(a), Type::Integer(b)) = (self, other) {
             return Ok(Type::Integer(a - b));
         }
 
-        if let (Type::Address(Address::StackPointer(i)), Type::Address(Address::StackPointer(j))) = (self, other) {
-            return Ok(Type::Address(Address::StackPointer(i - j)));
+        if let Some((a, b)) = float_operands(self, other) {
+            return Ok(Type::Float(a - b));
+        }
+
+        match (self, other) {
+            (Type::Char(a), Type::Integer(b)) => return Ok(Type::Integer(*a as isize - b)),
+            (Type::Integer(a), Type::Char(b)) => return Ok(Type::Integer(a - *b as isize)),
+            (Type::Char(a), Type::Char(b)) => return Ok(Type::Integer(*a as isize - *b as isize)),
+            (Type::String(a), Type::String(b)) => return Ok(Type::String(a.replacen(b.as_str(), "", 1))),
+            (Type::Address(a), Type::Integer(b)) => return Ok(Type::Address(a.try_add(&-b)?)),
+            _ => {}
+        }
+
+        if let (Type::Address(address @ Address::StackPointer(i)), Type::Address(Address::StackPointer(j))) = (self, other) {
+            let difference = i.checked_sub(*j).ok_or_else(|| TryOperateTypes::Underflow(address.disassemble(), -(*j as isize)))?;
+            return Ok(Type::Address(Address::StackPointer(difference)));
         }
 
         Err(OperationError::Subtraction(self.clone(), other.clone()))
     }
 
+    /// Integers wrap around on overflow. A repeated string may be at most `STRING_LIMIT` bytes long
+    pub fn mul(&self, other: &Type) -> Result<Type, OperationError> {
+        match (self, other, float_operands(self, other)) {
+            (Type::Integer(a), Type::Integer(b), _) => Ok(Type::Integer(a.wrapping_mul(*b))),
+            (_, _, Some((a, b))) => Ok(Type::Float(a * b)),
+            (Type::String(a), Type::Integer(b), _) => match usize::try_from(*b) {
+                Ok(count) => match a.len().checked_mul(count) {
+                    Some(length) if length <= STRING_LIMIT => Ok(Type::String(a.repeat(count))),
+                    _ => Err(OperationError::StringTooLong { length: a.len().saturating_mul(count), limit: STRING_LIMIT })
+                },
+                Err(_) => Err(OperationError::WrongType { expected: "non-negative Integer".to_string(), actual: format!("{other}") })
+            },
+            (a, b, None) => Err(OperationError::Multiplication(a.clone(), b.clone()))
+        }
+    }
+
+    /// Integers round towards zero. `isize::MIN / -1` wraps around to `isize::MIN`
+    pub fn div(&self, other: &Type) -> Result<Type, OperationError> {
+        match (self, other, float_operands(self, other)) {
+            (Type::Integer(_), Type::Integer(0), _) => Err(OperationError::DivideByZero),
+            (Type::Integer(a), Type::Integer(b), _) => Ok(Type::Integer(a.wrapping_div(*b))),
+            (_, _, Some((_, 0.0))) => Err(OperationError::DivideByZero),
+            (_, _, Some((a, b))) => Ok(Type::Float(a / b)),
+            (a, b, None) => Err(OperationError::Division(a.clone(), b.clone()))
+        }
+    }
+
+    /// The remainder has the sign of the dividend like Rust's `%`, so `-7 % 3` is `-1`
+    pub fn rem(&self, other: &Type) -> Result<Type, OperationError> {
+        match (self, other, float_operands(self, other)) {
+            (Type::Integer(_), Type::Integer(0), _) => Err(OperationError::DivideByZero),
+            (Type::Integer(a), Type::Integer(b), _) => Ok(Type::Integer(a.wrapping_rem(*b))),
+            (_, _, Some((_, 0.0))) => Err(OperationError::DivideByZero),
+            (_, _, Some((a, b))) => Ok(Type::Float(a % b)),
+            (a, b, None) => Err(OperationError::Remainder(a.clone(), b.clone()))
+        }
+    }
+
+    /// `-isize::MIN` wraps around to `isize::MIN`
+    #[allow(clippy::should_implement_trait)]
+    pub fn neg(&self) -> Result<Type, OperationError> {
+        match self {
+            Type::Integer(a) => Ok(Type::Integer(a.wrapping_neg())),
+            Type::Float(a) => Ok(Type::Float(-a)),
+            rest => Err(OperationError::WrongType { expected: "Integer or Float".to_string(), actual: format!("{rest}") })
+        }
+    }
+
+    pub fn and(&self, other: &Type) -> Result<Type, OperationError> {
+        self.bitwise(other, |a, b| a & b)
+    }
+
+    pub fn or(&self, other: &Type) -> Result<Type, OperationError> {
+        self.bitwise(other, |a, b| a | b)
+    }
+
+    pub fn xor(&self, other: &Type) -> Result<Type, OperationError> {
+        self.bitwise(other, |a, b| a ^ b)
+    }
+
+    /// Bitwise operations are only defined on integers
+    fn bitwise(&self, other: &Type, operation: fn(isize, isize) -> isize) -> Result<Type, OperationError> {
+        match (self, other) {
+            (Type::Integer(a), Type::Integer(b)) => Ok(Type::Integer(operation(*a, *b))),
+            (Type::Integer(_), rest) | (rest, _) => Err(OperationError::WrongType { expected: "Integer".to_string(), actual: format!("{rest}") })
+        }
+    }
+
+    #[allow(clippy::should_implement_trait)]
     pub fn cmp(&self, other: &Type) -> Result<Type, OperationError> {
+        // NaN isn't ordered and results in an error
+        if let Some(ordering) = float_operands(self, other).and_then(|(o1, o2)| o1.partial_cmp(&o2)) {
+            return Ok(Type::Integer(usize_from(ordering)));
+        }
+
         match (self, other) {
             (Type::Integer(o1), Type::Integer(o2)) => Ok(Type::Integer(usize_from(o1.cmp(o2)))),
+            (Type::Char(o1), Type::Char(o2)) => Ok(Type::Integer(usize_from(o1.cmp(o2)))),
+            (Type::Char(o1), Type::Integer(o2)) => Ok(Type::Integer(usize_from((*o1 as isize).cmp(o2)))),
+            (Type::Integer(o1), Type::Char(o2)) => Ok(Type::Integer(usize_from(o1.cmp(&(*o2 as isize))))),
 
             (Type::Address(Address::StackPointer(address)), Type::Integer(i))
                 => Ok(Type::Integer(usize_from((*address as isize).cmp(i)))),
@@ -104,9 +292,70 @@ impl Type {
         }
     }
 
+    /// Returns a number identifying the variant, used by `typeof`
+    pub fn type_tag(&self) -> isize {
+        match self {
+            Type::Untyped => 0,
+            Type::Integer(_) => 1,
+            Type::String(_) => 2,
+            Type::Address(_) => 3,
+            Type::Char(_) => 4,
+            Type::Array(_) => 5,
+            Type::Float(_) => 6,
+            Type::Boolean(_) => 7,
+        }
+    }
+
+    /// Returns the name of the variant, e.g. `Integer`
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Type::Untyped => "Untyped",
+            Type::Integer(_) => "Integer",
+            Type::String(_) => "String",
+            Type::Address(_) => "Address",
+            Type::Char(_) => "Char",
+            Type::Array(_) => "Array",
+            Type::Float(_) => "Float",
+            Type::Boolean(_) => "Boolean",
+        }
+    }
+
+    /// Returns a FNV-1a hash of the value, which is the same across runs and platforms
+    pub fn stable_hash(&self) -> Result<isize, OperationError> {
+        let bytes = match self {
+            Type::Integer(value) => (*value as i64).to_le_bytes().to_vec(),
+            Type::Float(value) => value.to_le_bytes().to_vec(),
+            Type::Boolean(value) => vec![u8::from(*value)],
+            Type::String(value) => value.as_bytes().to_vec(),
+            Type::Char(value) => value.to_string().into_bytes(),
+            rest => return Err(OperationError::WrongType { expected: "Integer, Float, Boolean, String or Char".to_string(), actual: format!("{rest}") })
+        };
+
+        // the type tag keeps e.g. 1 and "1" apart
+        let hash = std::iter::once(self.type_tag() as u8).chain(bytes).fold(0xcbf29ce484222325_u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+
+        Ok(hash as isize)
+    }
+
+    pub fn string_equal(&self, other: &Type) -> Result<bool, OperationError> {
+        match (self, other) {
+            (Type::String(a), Type::String(b)) => Ok(a == b),
+            (Type::String(_), rest) | (rest, _) => Err(OperationError::WrongType { expected: "String".to_string(), actual: format!("{rest}") })
+        }
+    }
+
     pub fn add(&self, other: &Type) -> Result<Type, OperationError> {
+        reject_code_addresses(self, other)?;
+
+        if let Some((o1, o2)) = float_operands(self, other) {
+            return Ok(Type::Float(o1 + o2));
+        }
+
         match (self, other) {
             (Type::Integer(o1), Type::Integer(o2)) => Ok(Type::Integer(o1 + o2)),
+            (Type::Char(c), Type::Integer(i)) | (Type::Integer(i), Type::Char(c)) => Ok(Type::Integer(*c as isize + i)),
 
             (Type::Address(addr), Type::Integer(i)) => Ok(Type::Address(addr.try_add(i)?)),
             (Type::Address(addr1), Type::Address(addr2)) => Ok(Type::Address(addr1.try_add(addr2)?)),
@@ -115,20 +364,146 @@ impl Type {
             (Type::Integer(a), Type::String(b)) => Ok(Type::String(format!("{a}{b}"))),
             (Type::String(a), Type::String(b)) => Ok(Type::String(format!("{a}{b}"))),
 
+            // booleans and arrays have no sum, and two characters aren't concatenated
+            (Type::Char(_), rest @ Type::Char(_)) => Err(OperationError::WrongType { expected: "Integer".to_string(), actual: format!("{rest}") }),
+            (rest @ (Type::Boolean(_) | Type::Array(_)), _) | (_, rest @ (Type::Boolean(_) | Type::Array(_))) => {
+                Err(OperationError::WrongType { expected: "Integer, Float, Char, String or Address".to_string(), actual: format!("{rest}") })
+            },
+
             (a, b) => Ok(Type::String(format!("{a}{b}", a = a.to_string_raw(), b = b.to_string_raw()))),
         }
     }
 
+    /// Returns the value in the syntax it is written in the source code
+    pub fn disassemble(&self) -> String {
+        match self {
+            Type::String(a) => format!("\"{}\"", escape_literal(a, '"')),
+            Type::Integer(a) => a.to_string(),
+            Type::Float(a) => format!("{a:?}"),
+            Type::Boolean(a) => a.to_string(),
+            Type::Char(a) => format!("'{}'", escape_literal(&a.to_string(), '\'')),
+            Type::Address(a) => a.disassemble(),
+            // arrays have no literal syntax
+            Type::Array(_) => self.to_string_raw(),
+            Type::Untyped => "".to_string(),
+        }
+    }
+
     pub fn to_string_raw(&self) -> String {
         match self {
             Type::String(a) => a.to_string(),
             Type::Integer(a) => a.to_string(),
+            // the shortest representation, which reads back as the same value, e.g. `3.5` or `4.0`
+            Type::Float(a) => format!("{a:?}"),
+            Type::Boolean(a) => a.to_string(),
+            Type::Char(a) => a.to_string(),
             Type::Address(a) => a.to_string(),
+            Type::Array(a) => format!("[{}]", a.iter().map(|a| a.to_string_raw()).collect::<Vec<_>>().join(", ")),
             Type::Untyped => "".to_string(),
         }
     }
 }
 
+/// Code addresses, e.g. from `lea rax pc`, can't be used in arithmetic. Otherwise they would
+/// silently turn into strings
+fn reject_code_addresses(a: &Type, b: &Type) -> Result<(), OperationError> {
+    match (a, b) {
+        (code_address @ Type::Address(Address::ProgramCounter), _) | (_, code_address @ Type::Address(Address::ProgramCounter)) => {
+            Err(OperationError::WrongType { expected: "data Address or value".to_string(), actual: format!("{code_address}") })
+        }
+        _ => Ok(())
+    }
+}
+
+/// Parses an integer literal in decimal or, with a `0x` or `0b` prefix, in hexadecimal or binary, e.g. `-0x10`.
+/// Digits can be separated by single underscores, e.g. `1_000_000`
+pub fn parse_integer(s: &str) -> Result<isize, ParseIntError> {
+    let (sign, unsigned) = match s.strip_prefix('-') {
+        Some(unsigned) => ('-', unsigned),
+        None => ('+', s.strip_prefix('+').unwrap_or(s))
+    };
+
+    let (radix, digits) = if let Some(hex) = unsigned.strip_prefix("0x") {
+        (16, hex)
+    } else if let Some(binary) = unsigned.strip_prefix("0b") {
+        (2, binary)
+    } else {
+        (10, unsigned)
+    };
+
+    // misplaced underscores are kept and rejected as invalid digits
+    let digits = if digits.starts_with('_') || digits.ends_with('_') || digits.contains("__") {
+        digits.to_string()
+    } else {
+        digits.replace('_', "")
+    };
+
+    // the explicit sign rejects a second sign after the prefix, e.g. `0x-1`
+    isize::from_str_radix(&format!("{sign}{digits}"), radix)
+}
+
+/// Parses a float written with a `.` or an exponent, as well as `inf`, `-inf` and `nan`.
+/// Integers like `3` aren't floats, so they're rejected
+pub fn parse_float(s: &str) -> Option<f64> {
+    let non_finite = matches!(s.trim_start_matches('-'), "inf" | "nan" | "NaN");
+    if !non_finite && !s.contains(['.', 'e', 'E']) {
+        return None;
+    }
+
+    s.parse::<f64>().ok()
+}
+
+/// Returns both operands as floats, if one is a float and the other one a float or an integer
+fn float_operands(a: &Type, b: &Type) -> Option<(f64, f64)> {
+    match (a, b) {
+        (Type::Float(a), Type::Float(b)) => Some((*a, *b)),
+        (Type::Float(a), Type::Integer(b)) => Some((*a, *b as f64)),
+        (Type::Integer(a), Type::Float(b)) => Some((*a as f64, *b)),
+        _ => None
+    }
+}
+
+/// Escape sequences of character and string literals, as pairs of the character after the backslash and the escaped character
+const ESCAPE_SEQUENCES: &[(char, char)] = &[('n', '\n'), ('t', '\t'), ('r', '\r'), ('0', '\0'), ('\'', '\''), ('"', '"'), ('\\', '\\')];
+
+/// Returns the character of an escape sequence, e.g. a newline for `n`
+fn unescape(sequence: char) -> Option<char> {
+    ESCAPE_SEQUENCES.iter().find(|(s, _)| *s == sequence).map(|(_, c)| *c)
+}
+
+/// Replaces the escape sequences in the content of a string literal
+fn unescape_string(content: &str, literal: &str) -> Result<String, ParseError> {
+    let mut result = String::new();
+    let mut chars = content.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(sequence) => result.push(unescape(sequence).ok_or_else(|| ParseError::new(&format!("Unknown escape sequence \\{sequence} in {literal}")))?),
+                // the backslash escapes the closing quote
+                None => return Err(ParseError::new(&format!("Unterminated string literal {literal}")))
+            },
+            '"' => return Err(ParseError::new(&format!("Quotes inside of a string literal must be escaped: {literal}"))),
+            c => result.push(c)
+        }
+    }
+
+    Ok(result)
+}
+
+/// Escapes the quote, backslashes and control characters, so the value can be parsed again inside of the quotes
+fn escape_literal(value: &str, quote: char) -> String {
+    value.chars().map(|c| match ESCAPE_SEQUENCES.iter().find(|(_, escaped)| *escaped == c) {
+        Some((sequence, _)) if c == quote || c == '\\' || c.is_control() => format!("\\{sequence}"),
+        _ => c.to_string()
+    }).collect()
+}
+
+/// Escapes control characters, so the value stays on a single line
+fn escape_control(value: &str) -> String {
+    value.chars().map(|c| if c.is_control() { c.escape_default().to_string() } else { c.to_string() }).collect()
+}
+
 fn usize_from(o: Ordering) -> isize {
     match o {
         Ordering::Less => -1,
@@ -141,29 +516,85 @@ impl FromStr for Assignment {
     type Err = ParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // checked first, since they would be parsed as symbols otherwise
+        match s.trim() {
+            "true" => return Ok(Assignment::Value(Type::Boolean(true))),
+            "false" => return Ok(Assignment::Value(Type::Boolean(false))),
+            _ => {}
+        }
+
+        // checked before addresses, since `inf` and `nan` would be parsed as symbols otherwise
+        if let Some(value) = parse_float(s) {
+            return Ok(Assignment::Value(Type::Float(value)));
+        }
+
         if let Ok(destination) = Address::from_str(s) {
             return Ok(Assignment::Address(destination));
         }
 
-        if let Ok(value) = s.parse::<isize>() {
-            return Ok(Assignment::Value(Type::Integer(value)));
+        match parse_integer(s) {
+            Ok(value) => return Ok(Assignment::Value(Type::Integer(value))),
+            Err(error) if matches!(error.kind(), IntErrorKind::PosOverflow | IntErrorKind::NegOverflow) => {
+                return Err(ParseError::new(&format!("Integer literal {s} is out of range")));
+            }
+            Err(_) if s.trim_start_matches('-').starts_with("0x") => {
+                return Err(ParseError::new(&format!("{s} is not a valid hexadecimal literal")));
+            }
+            Err(_) if s.trim_start_matches('-').starts_with("0b") => {
+                return Err(ParseError::new(&format!("{s} is not a valid binary literal")));
+            }
+            Err(_) => {}
         }
 
-        let binding = s.replace('"', " \" ");
-        let line = binding
-            .split(' ')
-            .collect::<Vec<_>>();
+        if let Some(character) = s.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')) {
+            let mut chars = character.chars();
 
-        if let ["\"", strings @ .., "\""] = &line[..] {
-            return Ok(Assignment::Value(Type::String(strings.join(" "))));
-        } else if let [_, "\"", strings @ .., "\"", _] = &line[..] {
-            return Ok(Assignment::Value(Type::String(strings.join(" "))));
+            return match (chars.next(), chars.next(), chars.next()) {
+                // the closing quote is escaped, e.g. `'\'`
+                (Some('\\'), None, None) => Err(ParseError::new(&format!("Unterminated character literal {s}"))),
+                (Some('\\'), Some(sequence), None) => match unescape(sequence) {
+                    Some(c) => Ok(Assignment::Value(Type::Char(c))),
+                    None => Err(ParseError::new(&format!("Unknown escape sequence \\{sequence} in {s}")))
+                },
+                (Some(c), None, None) => Ok(Assignment::Value(Type::Char(c))),
+                _ => Err(ParseError::new(&format!("{s} is not a single character")))
+            }
+        }
+
+        if s.starts_with('\'') {
+            return Err(ParseError::new(&format!("Unterminated character literal {s}")));
+        }
+
+        if let Some(string) = s.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+            return Ok(Assignment::Value(Type::String(unescape_string(string, s)?)));
+        }
+
+        if s.starts_with('"') {
+            return Err(ParseError::new(&format!("Unterminated string literal {s}")));
         }
 
         Err(ParseError::new(&format!("{s} cannot be parsed as an assignment")))
     }
 }
 
+impl Assignment {
+    /// Returns the value, if the assignment is known without reading memory
+    pub fn as_const(&self) -> Option<Type> {
+        match self {
+            Assignment::Value(value) => Some(value.clone()),
+            Assignment::Address(_) => None,
+        }
+    }
+
+    /// Returns the assignment in the syntax it is written in the source code
+    pub fn disassemble(&self) -> String {
+        match self {
+            Assignment::Value(value) => value.disassemble(),
+            Assignment::Address(address) => address.disassemble(),
+        }
+    }
+}
+
 impl Display for Assignment {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", match self {
@@ -171,4 +602,239 @@ impl Display for Assignment {
             Assignment::Address(destination) => format!("{destination}"),
         })
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use crate::register::Register;
+    use super::*;
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn address_type_survives_serde_round_trip() {
+        use crate::address::Destination;
+
+        let value = Type::Address(Address::Reference(Destination::StackPointer(3)));
+        let json = serde_json::to_string(&value).unwrap();
+
+        assert_eq!(json, r#"{"Address":{"Reference":{"StackPointer":3}}}"#);
+        assert_eq!(serde_json::from_str::<Type>(&json).unwrap(), value);
+    }
+
+    #[test]
+    fn only_values_are_constant() {
+        assert_eq!(Assignment::Value(Type::Integer(3)).as_const(), Some(Type::Integer(3)));
+        assert_eq!(Assignment::Address(Address::Register(Register::Rax)).as_const(), None);
+        assert_eq!(Assignment::Address(Address::StackPointer(2)).as_const(), None);
+    }
+
+    #[test]
+    fn char_displays_as_the_character() {
+        assert_eq!(Type::Char('A').to_string(), "Char 'A'");
+        assert_eq!(Type::Char('A').to_string_raw(), "A");
+        assert_eq!(Assignment::from_str("'A'").unwrap(), Assignment::Value(Type::Char('A')));
+    }
+
+    #[test]
+    fn char_and_integer_arithmetic_is_integer() {
+        assert_eq!(Type::Char('A').add(&Type::Integer(1)).unwrap(), Type::Integer(66));
+        assert_eq!(Type::Integer(1).add(&Type::Char('A')).unwrap(), Type::Integer(66));
+        assert_eq!(Type::Char('B').sub(&Type::Integer(1)).unwrap(), Type::Integer(65));
+        assert_eq!(Type::Char('B').sub(&Type::Char('A')).unwrap(), Type::Integer(1));
+    }
+
+    #[test]
+    fn string_subtraction_removes_the_first_occurrence() {
+        let text = Type::String("hello world world".to_string());
+
+        assert_eq!(text.sub(&Type::String(" world".to_string())).unwrap(), Type::String("hello world".to_string()));
+        assert_eq!(text.sub(&Type::String("moon".to_string())).unwrap(), text);
+    }
+
+    #[test]
+    fn display_escapes_control_characters_but_raw_output_doesnt() {
+        let value = Type::String("a\nb\tc".to_string());
+
+        assert_eq!(value.to_string(), "String 'a\\nb\\tc'");
+        assert_eq!(value.to_string_raw(), "a\nb\tc");
+        assert_eq!(Type::String("ä".to_string()).to_string(), "String 'ä'");
+    }
+
+    #[test]
+    fn subtracting_from_a_stack_address_moves_it_down() {
+        let address = Type::Address(Address::StackPointer(5));
+
+        assert_eq!(address.sub(&Type::Integer(2)).unwrap(), Type::Address(Address::StackPointer(3)));
+        assert_eq!(address.sub(&Type::Address(Address::StackPointer(2))).unwrap(), Type::Address(Address::StackPointer(3)));
+    }
+
+    #[test]
+    fn subtracting_below_zero_underflows() {
+        let address = Type::Address(Address::StackPointer(1));
+
+        assert!(matches!(address.sub(&Type::Integer(2)), Err(OperationError::TryAdd(TryOperateTypes::Underflow(_, -2)))));
+        assert!(matches!(address.sub(&Type::Address(Address::StackPointer(3))), Err(OperationError::TryAdd(TryOperateTypes::Underflow(_, -3)))));
+    }
+
+    #[test]
+    fn stack_address_difference_underflow_is_a_runtime_error() {
+        let error = crate::test_utils::run("lea rax sp[1]\nlea rbx sp[3]\nsub rcx rax rbx\nret 0").unwrap_err();
+        assert!(error.to_string().starts_with("at instruction 2 (line 3)"), "{error}");
+    }
+
+    #[test]
+    fn arithmetic_on_code_addresses_is_rejected() {
+        let code_address = Type::Address(Address::ProgramCounter);
+
+        assert!(matches!(code_address.add(&Type::String("x".to_string())), Err(OperationError::WrongType { .. })));
+        assert!(matches!(code_address.add(&Type::Integer(1)), Err(OperationError::WrongType { .. })));
+        assert!(matches!(Type::Integer(1).sub(&code_address), Err(OperationError::WrongType { .. })));
+    }
+
+    #[test]
+    fn overflowing_and_malformed_literals_have_different_messages() {
+        let overflow = Assignment::from_str("99999999999999999999").unwrap_err().to_string();
+        assert!(overflow.contains("Integer literal 99999999999999999999 is out of range"), "{overflow}");
+        assert!(Assignment::from_str("-99999999999999999999").unwrap_err().to_string().contains("out of range"));
+
+        let malformed = Assignment::from_str("12a").unwrap_err().to_string();
+        assert!(malformed.contains("12a cannot be parsed as an assignment"), "{malformed}");
+    }
+
+    #[test]
+    fn stable_hash_is_deterministic() {
+        let hash = Type::String("abc".to_string()).stable_hash().unwrap();
+
+        assert_eq!(Type::String("abc".to_string()).stable_hash().unwrap(), hash);
+        assert_ne!(Type::String("abd".to_string()).stable_hash().unwrap(), hash);
+        assert_ne!(Type::Integer(1).stable_hash().unwrap(), Type::String("1".to_string()).stable_hash().unwrap());
+    }
+
+    #[test]
+    fn untyped_and_addresses_are_not_hashable() {
+        assert!(matches!(Type::Untyped.stable_hash(), Err(OperationError::WrongType { .. })));
+        assert!(matches!(Type::Address(Address::StackPointer(0)).stable_hash(), Err(OperationError::WrongType { .. })));
+    }
+
+    #[test]
+    fn floats_parse_in_scientific_notation() {
+        assert_eq!(Assignment::from_str("1e3").unwrap(), Assignment::Value(Type::Float(1000.0)));
+        assert_eq!(Assignment::from_str("2.5e-2").unwrap(), Assignment::Value(Type::Float(0.025)));
+        assert_eq!(Assignment::from_str("-1E22").unwrap(), Assignment::Value(Type::Float(-1e22)));
+        assert_eq!(Assignment::from_str("3").unwrap(), Assignment::Value(Type::Integer(3)));
+        assert_eq!(Assignment::from_str("0xE").unwrap(), Assignment::Value(Type::Integer(14)));
+        assert_eq!(Assignment::from_str("message").unwrap(), Assignment::Address(Address::Symbol("message".to_string())));
+    }
+
+    #[test]
+    fn inf_and_nan_are_floats() {
+        assert_eq!(Assignment::from_str("inf").unwrap(), Assignment::Value(Type::Float(f64::INFINITY)));
+        assert_eq!(Assignment::from_str("-inf").unwrap(), Assignment::Value(Type::Float(f64::NEG_INFINITY)));
+        assert!(matches!(Assignment::from_str("nan").unwrap(), Assignment::Value(Type::Float(value)) if value.is_nan()));
+
+        assert!(Type::Float(f64::NAN).cmp(&Type::Float(1.0)).is_err());
+        assert_eq!(Type::Float(f64::INFINITY).cmp(&Type::Integer(1)).unwrap(), Type::Integer(1));
+    }
+
+    #[test]
+    fn mul_multiplies_integers_and_repeats_strings() {
+        assert_eq!(Type::Integer(6).mul(&Type::Integer(-7)).unwrap(), Type::Integer(-42));
+        assert_eq!(Type::String("ab".to_string()).mul(&Type::Integer(3)).unwrap(), Type::String("ababab".to_string()));
+        assert_eq!(Type::String("ab".to_string()).mul(&Type::Integer(0)).unwrap(), Type::String(String::new()));
+        assert!(matches!(Type::String("ab".to_string()).mul(&Type::Integer(-1)), Err(OperationError::WrongType { .. })));
+        assert_eq!(Type::Integer(isize::MAX).mul(&Type::Integer(2)).unwrap(), Type::Integer(-2));
+        assert_eq!(Type::String("a".to_string()).mul(&Type::Integer(STRING_LIMIT as isize)).unwrap().to_string_raw().len(), STRING_LIMIT);
+        assert!(matches!(Type::String("ab".to_string()).mul(&Type::Integer(isize::MAX)), Err(OperationError::StringTooLong { length, limit: STRING_LIMIT }) if length == usize::MAX - 1));
+        assert!(matches!(Type::String("abc".to_string()).mul(&Type::Integer(isize::MAX)), Err(OperationError::StringTooLong { length: usize::MAX, .. })));
+
+        let stack = Type::Address(Address::StackPointer(1));
+        assert!(matches!(stack.mul(&stack), Err(OperationError::Multiplication(_, _))));
+    }
+
+    #[test]
+    fn floats_display_shortest_and_disassemble_to_themselves() {
+        assert_eq!(Type::Float(3.5).to_string_raw(), "3.5");
+        assert_eq!(Type::Float(4.0).to_string_raw(), "4.0");
+        assert_eq!(format!("{:?}", Type::Float(0.1 + 0.2)), "0.30000000000000004");
+
+        for value in [3.5, 4.0, -0.25, 1e17, 1e22, 1e-7, f64::INFINITY, f64::NEG_INFINITY] {
+            let disassembly = Type::Float(value).disassemble();
+            assert_eq!(Assignment::from_str(&disassembly).unwrap(), Assignment::Value(Type::Float(value)), "{disassembly}");
+        }
+    }
+
+    #[test]
+    fn float_literals_beyond_the_integer_range_are_floats() {
+        assert_eq!(Assignment::from_str("10000000000000000000000.0").unwrap(), Assignment::Value(Type::Float(1e22)));
+        assert!(Assignment::from_str("10000000000000000000000").unwrap_err().to_string().contains("out of range"));
+    }
+
+    #[test]
+    fn add_rejects_booleans_arrays_and_two_chars() {
+        let wrong_type = |a: Type, b: Type| matches!(a.add(&b), Err(OperationError::WrongType { .. }));
+
+        assert!(wrong_type(Type::Boolean(true), Type::Integer(1)));
+        assert!(wrong_type(Type::String("a".to_string()), Type::Boolean(false)));
+        assert!(wrong_type(Type::Array(vec![]), Type::Integer(1)));
+        assert!(wrong_type(Type::Integer(1), Type::Array(vec![Type::Integer(2)])));
+        assert!(wrong_type(Type::Char('a'), Type::Char('b')));
+
+        assert_eq!(Type::String("a".to_string()).add(&Type::Char('b')).unwrap(), Type::String("ab".to_string()));
+    }
+
+    #[test]
+    fn booleans_parse_and_display() {
+        assert_eq!(Assignment::from_str("true").unwrap(), Assignment::Value(Type::Boolean(true)));
+        assert_eq!(Assignment::from_str("false").unwrap(), Assignment::Value(Type::Boolean(false)));
+        assert_eq!(Type::Boolean(true).to_string_raw(), "true");
+        assert_eq!(format!("{:?}", Type::Boolean(false)), "false");
+        assert_eq!(Type::Boolean(true).to_string(), "Boolean 'true'");
+        assert_eq!(Type::Boolean(false).disassemble(), "false");
+    }
+
+    #[test]
+    fn hexadecimal_literals_parse_as_integers() {
+        assert_eq!(Assignment::from_str("0x1F").unwrap(), Assignment::Value(Type::Integer(31)));
+        assert_eq!(Assignment::from_str("-0x10").unwrap(), Assignment::Value(Type::Integer(-16)));
+        assert_eq!(Assignment::from_str("0x1e3").unwrap(), Assignment::Value(Type::Integer(0x1e3)));
+
+        for invalid in ["0xZZ", "0x-1", "0x"] {
+            assert!(Assignment::from_str(invalid).unwrap_err().to_string().contains("is not a valid hexadecimal literal"), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn binary_and_underscore_separated_literals_parse() {
+        for (literal, value) in [("0b1010", 10), ("-0b1", -1), ("1_000_000", 1_000_000), ("0xFF_FF", 0xFFFF), ("0b1010_0101", 0b1010_0101), ("+5", 5)] {
+            assert_eq!(Assignment::from_str(literal).unwrap(), Assignment::Value(Type::Integer(value)), "{literal}");
+        }
+
+        assert!(Assignment::from_str("0b12").unwrap_err().to_string().contains("is not a valid binary literal"));
+        for invalid in ["1__0", "1_", "0x_1", "0b1_"] {
+            assert!(Assignment::from_str(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn character_literals_parse_with_escape_sequences() {
+        for (literal, c) in [("'A'", 'A'), ("'\\n'", '\n'), ("'\\t'", '\t'), ("'\\0'", '\0'), ("'\\''", '\''), ("'\\\\'", '\\'), ("'é'", 'é')] {
+            assert_eq!(Assignment::from_str(literal).unwrap(), Assignment::Value(Type::Char(c)), "{literal}");
+            assert_eq!(Type::Char(c).disassemble(), literal);
+        }
+
+        for (invalid, message) in [("'ab'", "is not a single character"), ("'a", "Unterminated character literal"), ("'\\'", "Unterminated character literal"), ("'\\q'", "Unknown escape sequence \\q")] {
+            assert!(Assignment::from_str(invalid).unwrap_err().to_string().contains(message), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn string_literals_decode_escape_sequences() {
+        for (literal, value) in [(r#""line\nbreak""#, "line\nbreak"), (r#""a\tb""#, "a\tb"), (r#""back\\slash""#, "back\\slash"), (r#""say \"hi\"""#, "say \"hi\"")] {
+            assert_eq!(Assignment::from_str(literal).unwrap(), Assignment::Value(Type::String(value.to_string())), "{literal}");
+            assert_eq!(Type::String(value.to_string()).disassemble(), literal);
+        }
+
+        for (invalid, message) in [(r#""abc\""#, "Unterminated string literal"), (r#""\""#, "Unterminated string literal"), (r#""\q""#, "Unknown escape sequence \\q"), (r#""a"b""#, "must be escaped")] {
+            assert!(Assignment::from_str(invalid).unwrap_err().to_string().contains(message), "{invalid}");
+        }
+    }
+}
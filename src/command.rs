@@ -1,17 +1,28 @@
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
 use std::str::FromStr;
-use crate::assignment::{Assignment, OperationError, Type};
-use crate::address::{Address};
+use crate::assignment::{Assignment, OperationError, Type, TYPE_NAMES};
+use crate::address::{Address, TryAdd};
 use crate::interpreter::{StackFrame};
 use crate::jump::JumpDestination;
-use crate::memory::{Memory, MemoryError};
+use crate::memory::{Flags, Memory, MemoryError, ARRAY_LIMIT, STRING_LIMIT};
 use crate::program_error::ParseError;
+use crate::register::Register;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// All commands the assembly language supports at the moment
+///
+/// `mov`, `add` and `sub` can be written in the infix syntax of their documentation as well, e.g. `rax = rbx + 1`
 pub enum Command {
     /// Copying the assignment to the address, basically
     ///
     /// `address = assignment;`
+    ///
+    /// The value keeps its type, so any register can be spilled to the stack and reloaded unchanged
+    ///
+    /// `mov pc N` continues with the instruction after index N, so the index of a label
+    /// behaves exactly like jumping to the label, just without building a stack frame
     Mov(Address, Assignment),
     /// Adding the first assignment to the second assignment and storing the result in the address
     ///
@@ -21,152 +32,795 @@ pub enum Command {
     ///
     /// `address = assignment1 - assignment2`
     Sub(Address, Assignment, Assignment),
+    /// Multiplying the first assignment with the second assignment and storing the result in the address.
+    /// A string multiplied with an integer is repeated
+    ///
+    /// `address = assignment1 * assignment2`
+    Mul(Address, Assignment, Assignment),
+    /// Dividing the first assignment by the second assignment and storing the result in the address.
+    /// The result is rounded towards zero, dividing by zero fails
+    ///
+    /// `address = assignment1 / assignment2`
+    Div(Address, Assignment, Assignment),
+    /// Storing the remainder of dividing the first assignment by the second assignment in the address.
+    /// The remainder has the sign of the first assignment, dividing by zero fails
+    ///
+    /// `address = assignment1 % assignment2`
+    Mod(Address, Assignment, Assignment),
+    /// Bitwise and of two integers, stored in the address
+    ///
+    /// `address = assignment1 & assignment2`
+    And(Address, Assignment, Assignment),
+    /// Bitwise or of two integers, stored in the address
+    ///
+    /// `address = assignment1 | assignment2`
+    Or(Address, Assignment, Assignment),
+    /// Bitwise exclusive or of two integers, stored in the address
+    ///
+    /// `address = assignment1 ^ assignment2`
+    Xor(Address, Assignment, Assignment),
+    /// Negates the integer in the assignment and stores it in the address
+    ///
+    /// `address = -assignment`
+    Neg(Address, Assignment),
+    /// Decrements the stack pointer and writes the assignment into the slot at it. The stack grows down from its end,
+    /// so pushing only overwrites slots like `sp[0]` once the rest of the stack is full
+    Push(Assignment),
+    /// Moves the value of the slot at the stack pointer into the address and increments the stack pointer
+    Pop(Address),
     /// Loading the effective address from the second parameter and storing it in the first address
     ///
     /// `address1 = &address2`
     LoadEffectiveAddress(Address, Address),
-    /// call will build a stack frame
-    CallRet(Address, JumpDestination),
+    /// call will build a stack frame. Routines returning multiple values are called with the
+    /// destinations separated by commas, e.g. `call rax,rcx label`
+    CallRet(Vec<Address>, JumpDestination),
     CallVoid(JumpDestination),
-    /// jmp will just jump without storing and restoring rax, rbx, rcx
+    /// jmp will just jump without storing and restoring rax, rbx, rcx, rdx
     Jmp(JumpDestination),
-    /// A Label is a marker you jan jump to or call
-    Label(String),
-    Return(Assignment),
-    /// Special methods callable and provided by os kernel (printf)
-    Syscall(JumpDestination),
+    /// A Label is a marker you jan jump to or call. It may declare the type it returns, e.g. `func -> Integer:`
+    Label(String, Option<String>),
+    /// Returns the assignment to the callers address. Any assignment can be returned, e.g. `ret 5`,
+    /// `ret sp[3]` or `ret [rax]`. Multiple assignments (`ret rax rbx`) are returned to the callers addresses in order
+    Return(Vec<Assignment>),
+    /// Special methods callable and provided by os kernel (printf, printi, prints, depth, malloc, free, hash, backtrace, spawn, getenv, dumpstack, dumpregs, yield, sleep, time, read)
+    ///
+    /// The arguments are read from rax and rbx, unless they are passed inline, e.g. `syscall printf "{} + {}" 1 2`
+    Syscall(JumpDestination, Vec<Assignment>),
     Leave,
-    /// Jump, if Assignment == -1
-    JumpLess(Assignment, JumpDestination),
-    /// Jump, if Assignment == 1
-    JumpGreater(Assignment, JumpDestination),
-    /// Jump, if Assignment != 0
-    JumpNotEqual(Assignment, JumpDestination),
-    /// Jump, if Assignment == 0
-    JumpEqual(Assignment, JumpDestination),
-    /// Compares two Assignments and store the result in the Address
-    ///
-    /// `address = Sign(a2 - a1)`
-    Compare(Address, Assignment, Assignment)
+    /// Jump, if Assignment == -1. Without an Assignment, the flags are used
+    JumpLess(Option<Assignment>, JumpDestination),
+    /// Jump, if Assignment == 1. Without an Assignment, the flags are used
+    JumpGreater(Option<Assignment>, JumpDestination),
+    /// Jump, if Assignment <= 0. Without an Assignment, the flags are used
+    JumpLessEqual(Option<Assignment>, JumpDestination),
+    /// Jump, if Assignment >= 0. Without an Assignment, the flags are used
+    JumpGreaterEqual(Option<Assignment>, JumpDestination),
+    /// Jump, if Assignment != 0. Without an Assignment, the flags are used
+    JumpNotEqual(Option<Assignment>, JumpDestination),
+    /// Jump, if Assignment == 0. Without an Assignment, the flags are used
+    ///
+    /// The assignment is usually the result of a `cmp`, e.g. `cmp rcx rax 5` followed by `je rcx label`
+    JumpEqual(Option<Assignment>, JumpDestination),
+    /// Compares two Assignments and store the result in the Address. The flags are updated as well
+    ///
+    /// `address = Sign(a1 - a2)`, so `cmp rax 3 5` stores -1. Without an Address, only the flags are updated,
+    /// e.g. `cmp rax 0` followed by `je label`
+    Compare(Option<Address>, Assignment, Assignment),
+    /// Stores 1 in the Address if the zero flag is set, 0 otherwise. With `boolean_comparisons`, `true` or `false` is stored
+    SetEqual(Address),
+    /// Stores 1 in the Address if the zero flag is not set, 0 otherwise
+    SetNotEqual(Address),
+    /// Stores 1 in the Address if the sign flag is set, 0 otherwise
+    SetLess(Address),
+    /// Stores 1 in the Address if neither the zero nor the sign flag is set, 0 otherwise
+    SetGreater(Address),
+    /// Copies the assignment to the address, if the zero flag is set
+    MoveEqual(Address, Assignment),
+    /// Copies the assignment to the address, if the zero flag is not set
+    MoveNotEqual(Address, Assignment),
+    /// Copies the assignment to the address, if the sign flag is set
+    MoveLess(Address, Assignment),
+    /// Copies the assignment to the address, if neither the zero nor the sign flag is set
+    MoveGreater(Address, Assignment),
+    /// Compares two strings and stores 1 in the Address, if they are equal, 0 otherwise. With `boolean_comparisons`,
+    /// `true` or `false` is stored
+    ///
+    /// `address = a1 == a2`
+    StringEqual(Address, Assignment, Assignment),
+    /// Stores the type tag of the assignment in the address: 0 = Untyped, 1 = Integer, 2 = String, 3 = Address, 4 = Char, 5 = Array, 6 = Float, 7 = Boolean
+    TypeOf(Address, Assignment),
+    /// Jumps to the label at the index stored in the Assignment. Falls through, if the index is out of range
+    JumpTable(Assignment, Vec<JumpDestination>),
+    /// Shifts the registers cyclically: rax -> rbx -> rcx -> rax
+    RotateRegisters,
+    /// Does nothing
+    Nop,
+    /// Appends the assignment as raw string onto the string in the address, without copying it.
+    /// An untyped address is treated like an empty string. The result may be at most `STRING_LIMIT` bytes long
+    ///
+    /// `address += assignment`
+    Append(Address, Assignment),
+    /// Reads the element at the index of the array starting at the base address. Fails, if the index
+    /// lies outside of the stack or the allocated heap region
+    ///
+    /// `address = base[index]`
+    LoadIndexed(Address, Assignment, Assignment),
+    /// Stores a new array of the length with untyped elements in the address
+    ArrayNew(Address, Assignment),
+    /// Reads the element at the index of the array
+    ///
+    /// `address = array[index]`
+    ArrayGet(Address, Assignment, Assignment),
+    /// Writes the value at the index of the array stored in the address
+    ///
+    /// `address[index] = value`
+    ArraySet(Address, Assignment, Assignment),
+    /// Copies the assignments into consecutive slots starting at the address, e.g. `.data sp[0] 1 2 3 4`
+    Data(Address, Vec<Assignment>),
+    /// Pops the amount of stack frames at once and continues after the call of the last popped frame,
+    /// restoring its registers. Return values are discarded
+    Unwind(usize),
+    /// Clears the flags, like at the start of the program. Until the next `cmp`, conditional jumps without
+    /// an operand don't branch
+    ClearFlags,
+    /// Defines a read-only string, which is loaded with its symbol, e.g. `.string msg "hello"` and `mov rax msg`.
+    /// The strings are defined before the program starts
+    StringData(String, String),
+    /// Halts the program with the first assignment as exit code, if both assignments are equal
+    HaltIf(Assignment, Assignment),
+    /// Reads the raw UTF-8 byte at the byte offset of the string as integer from 0 to 255.
+    /// Unlike characters, a multi-byte character spans multiple offsets
+    ///
+    /// `address = string.bytes[index]`
+    GetByte(Address, Assignment, Assignment),
+    /// Converts the stack address in the assignment to its index as integer
+    AddressToInteger(Address, Assignment),
+    /// Converts the integer in the assignment to the stack address at this index. Fails, if it lies outside of the stack
+    IntegerToAddress(Address, Assignment),
+    /// Stores the registers and the flags under the name on the interpreter, overwriting a previous context with this name
+    SaveContext(String),
+    /// Restores the registers and the flags saved under the name
+    LoadContext(String),
 }
 
 impl Command {
+    /// Name of the instruction as written in the program, e.g. `mov`. Labels are named `label`
+    pub fn mnemonic(&self) -> String {
+        match self {
+            Command::Label(_, _) => "label".to_string(),
+            command => command.to_string().split_whitespace().next().unwrap_or_default().to_string()
+        }
+    }
+
+    /// Returns every address the command reads or writes, including the addresses its assignments read
+    pub fn addresses(&self) -> Vec<&Address> {
+        let (destinations, assignments): (Vec<&Address>, Vec<&Assignment>) = match self {
+            Command::Mov(destination, assignment) | Command::Neg(destination, assignment) |
+            Command::MoveEqual(destination, assignment) | Command::MoveNotEqual(destination, assignment) |
+            Command::MoveLess(destination, assignment) | Command::MoveGreater(destination, assignment) |
+            Command::TypeOf(destination, assignment) | Command::Append(destination, assignment) |
+            Command::ArrayNew(destination, assignment) | Command::AddressToInteger(destination, assignment) |
+            Command::IntegerToAddress(destination, assignment) => (vec![destination], vec![assignment]),
+            Command::Add(destination, operand1, operand2) | Command::Sub(destination, operand1, operand2) |
+            Command::Mul(destination, operand1, operand2) | Command::Div(destination, operand1, operand2) |
+            Command::Mod(destination, operand1, operand2) | Command::And(destination, operand1, operand2) |
+            Command::Or(destination, operand1, operand2) | Command::Xor(destination, operand1, operand2) |
+            Command::StringEqual(destination, operand1, operand2) | Command::LoadIndexed(destination, operand1, operand2) |
+            Command::ArrayGet(destination, operand1, operand2) | Command::ArraySet(destination, operand1, operand2) |
+            Command::GetByte(destination, operand1, operand2) => (vec![destination], vec![operand1, operand2]),
+            Command::Compare(destination, operand1, operand2) => (destination.iter().collect(), vec![operand1, operand2]),
+            Command::HaltIf(operand1, operand2) => (vec![], vec![operand1, operand2]),
+            Command::Pop(destination) | Command::SetEqual(destination) | Command::SetNotEqual(destination) |
+            Command::SetLess(destination) | Command::SetGreater(destination) => (vec![destination], vec![]),
+            Command::LoadEffectiveAddress(destination, source) => (vec![destination, source], vec![]),
+            Command::CallRet(destinations, _) => (destinations.iter().collect(), vec![]),
+            Command::Data(destination, assignments) => (vec![destination], assignments.iter().collect()),
+            Command::Push(assignment) | Command::JumpTable(assignment, _) => (vec![], vec![assignment]),
+            Command::Return(assignments) | Command::Syscall(_, assignments) => (vec![], assignments.iter().collect()),
+            Command::JumpLess(assignment, _) | Command::JumpGreater(assignment, _) |
+            Command::JumpLessEqual(assignment, _) | Command::JumpGreaterEqual(assignment, _) |
+            Command::JumpNotEqual(assignment, _) | Command::JumpEqual(assignment, _) => (vec![], assignment.iter().collect()),
+            Command::CallVoid(_) | Command::Jmp(_) | Command::Label(_, _) | Command::Leave |
+            Command::RotateRegisters | Command::Nop | Command::Unwind(_) | Command::ClearFlags |
+            Command::StringData(_, _) | Command::SaveContext(_) | Command::LoadContext(_) => (vec![], vec![])
+        };
+
+        destinations.into_iter()
+            .chain(assignments.into_iter().filter_map(|assignment| match assignment {
+                Assignment::Address(address) => Some(address),
+                Assignment::Value(_) => None
+            }))
+            .collect()
+    }
+
     /// Returns an optional, if some, containing a return value
     pub fn execute(&self, memory: &mut Memory, program_pointer: usize) -> Result<(), MemoryError> {
         match self {
+            // handled by the interpreter, since it owns the program pointer
+            Command::Mov(Address::ProgramCounter, _) => {},
             Command::Mov(destination, assigment) => {
                 memory.set(destination, memory.get(assigment)?)?;
             }
-            Command::Add(destination, operand1, operand2) => {
-                let result = memory.get(operand1)?.add(&memory.get(operand2)?)?;
-                memory.set(destination, result)?;
+            Command::Add(destination, operand1, operand2) => arithmetic(memory, destination, operand1, operand2, Type::add)?,
+            Command::Sub(destination, operand1, operand2) => arithmetic(memory, destination, operand1, operand2, Type::sub)?,
+            Command::Mul(destination, operand1, operand2) => arithmetic(memory, destination, operand1, operand2, Type::mul)?,
+            Command::Div(destination, operand1, operand2) => arithmetic(memory, destination, operand1, operand2, Type::div)?,
+            Command::Mod(destination, operand1, operand2) => arithmetic(memory, destination, operand1, operand2, Type::rem)?,
+            Command::And(destination, operand1, operand2) => arithmetic(memory, destination, operand1, operand2, Type::and)?,
+            Command::Or(destination, operand1, operand2) => arithmetic(memory, destination, operand1, operand2, Type::or)?,
+            Command::Xor(destination, operand1, operand2) => arithmetic(memory, destination, operand1, operand2, Type::xor)?,
+            Command::Push(source) => {
+                let Some(stack_pointer) = memory.stack_pointer.checked_sub(1) else {
+                    return Err(MemoryError::SegmentationFault(format!("Cannot push {}, the stack is full", source.disassemble())));
+                };
+
+                memory.set(&Address::StackPointer(stack_pointer), memory.get(source)?)?;
+                memory.stack_pointer = stack_pointer;
+            },
+            Command::Pop(destination) => {
+                if memory.stack_pointer >= memory.stack.len() {
+                    return Err(MemoryError::SegmentationFault(format!("Cannot pop into {}, the stack is empty", destination.disassemble())));
+                }
+
+                let value = memory.get(&Assignment::Address(Address::StackPointer(memory.stack_pointer)))?;
+                memory.stack_pointer += 1;
+                memory.set(destination, value)?;
             },
-            Command::Sub(destination, operand1, operand2) => {
-                let result = memory.get(operand1)?.sub(&memory.get(operand2)?)?;
+            Command::Neg(destination, source) => {
+                let result = memory.get(source)?.neg().map_err(|error| operand_error(error, &[source]))?;
+                if memory.flag_arithmetic {
+                    memory.flags.update(&result);
+                }
                 memory.set(destination, result)?;
-            }
-            Command::CallRet(destination, JumpDestination::Label(_)) => {
+            },
+            Command::CallRet(destinations, _) => {
                 let stack_frame = StackFrame {
                     return_address: program_pointer,
                     entered_with_jmp: false,
-                    destination: Some(destination.clone()),
+                    destinations: destinations.clone(),
                     register_state: memory.register_state(),
                 };
 
                 memory.stack_frame.push(stack_frame);
             }
-            Command::CallVoid(JumpDestination::Label(_)) => {
+            Command::CallVoid(_) => {
                 let stack_frame = StackFrame {
                     return_address: program_pointer,
                     entered_with_jmp: false,
-                    destination: None,
+                    destinations: Vec::new(),
                     register_state: memory.register_state(),
                 };
 
                 memory.stack_frame.push(stack_frame);
             },
             Command::JumpLess(_, _) | Command::JumpGreater(_, _) |
+            Command::JumpLessEqual(_, _) | Command::JumpGreaterEqual(_, _) |
             Command::JumpNotEqual(_, _) | Command::JumpEqual(_, _) |
-            Command::Jmp(JumpDestination::Label(_)) => {
+            Command::JumpTable(_, _) | Command::Jmp(_) => {
                 let stack_frame = StackFrame {
                     return_address: program_pointer,
                     entered_with_jmp: true,
-                    destination: None,
+                    destinations: Vec::new(),
                     register_state: memory.register_state(),
                 };
 
                 memory.stack_frame.push(stack_frame);
             }
-            Command::Syscall(JumpDestination::Label(label)) => {
-                if *label == "printf" {
-                    match &memory.rax {
+            Command::Syscall(JumpDestination::Label(label), inline_arguments) => {
+                let arguments = memory.syscall_arguments(inline_arguments)?;
+
+                match label.as_str() {
+                    "printf" => match &arguments[0] {
                         Type::String(format) => {
-                            let final_str = if format.contains("{}") {
-                                format.replace("{}", &memory.rbx.to_string_raw())
+                            if memory.strict_printf {
+                                let placeholders = format.matches("{}").count();
+                                // without inline arguments, an untyped rbx counts as no argument
+                                let provided = if inline_arguments.is_empty() {
+                                    usize::from(arguments[1] != Type::Untyped)
+                                } else {
+                                    arguments.len() - 1
+                                };
+
+                                if placeholders != provided {
+                                    return Err(MemoryError::FormatMismatch { placeholders, arguments: provided });
+                                }
+                            }
+
+                            // inline arguments replace the placeholders one after another
+                            let final_str = if !inline_arguments.is_empty() {
+                                arguments[1..].iter().fold(format.to_string(), |format, argument| format.replacen("{}", &argument.to_string_raw(), 1))
+                            } else if format.contains("{}") {
+                                format.replace("{}", &arguments[1].to_string_raw())
                             } else {
                                 format.to_string()
                             };
 
-                            println!("{final_str}");
+                            memory.output.println(&final_str)?;
                         }
                         rest => return Err(OperationError::WrongType { expected: "String".to_string(), actual: format!("{rest}") }.into())
-                    }
+                    },
+                    // prints rax without a format string
+                    "printi" => match &arguments[0] {
+                        Type::Integer(value) => memory.output.println(&value.to_string())?,
+                        rest => return Err(OperationError::WrongType { expected: "Integer".to_string(), actual: format!("{rest}") }.into())
+                    },
+                    "prints" => match &arguments[0] {
+                        Type::String(value) => memory.output.println(value)?,
+                        rest => return Err(OperationError::WrongType { expected: "String".to_string(), actual: format!("{rest}") }.into())
+                    },
+                    // stores a hash of rax into rbx, which is the same across runs, e.g. for memo tables
+                    "hash" => memory.set_register(Register::Rbx, Type::Integer(arguments[0].stable_hash()?)),
+                    // stores the instructions, which entered the current stack frames, into rax, innermost first
+                    "backtrace" => {
+                        let backtrace = memory.stack_frame.iter().rev()
+                            .map(|stack_frame| format!("{} at {}", if stack_frame.entered_with_jmp { "jmp" } else { "call" }, stack_frame.return_address))
+                            .collect::<Vec<_>>();
+
+                        memory.set_register(Register::Rax, Type::String(backtrace.join(" <- ")));
+                    },
+                    // stores the amount of stack frames into rax, e.g. to limit recursion
+                    "depth" => memory.set_register(Register::Rax, Type::Integer(memory.stack_frame.len() as isize)),
+                    // allocates rax slots on the heap and stores the base address in rax
+                    "malloc" => match &arguments[0] {
+                        Type::Integer(size) if *size > 0 => {
+                            let base = memory.allocate(*size as usize)?;
+                            memory.set_register(Register::Rax, Type::Address(base))
+                        },
+                        rest => return Err(OperationError::WrongType { expected: "positive Integer".to_string(), actual: format!("{rest}") }.into())
+                    },
+                    "free" => match &arguments[0] {
+                        Type::Address(Address::HeapPointer(base)) => memory.free(*base)?,
+                        rest => return Err(OperationError::WrongType { expected: "heap Address".to_string(), actual: format!("{rest}") }.into())
+                    },
+                    // advances the simulated clock by rax milliseconds, without actually sleeping
+                    "sleep" => match &arguments[0] {
+                        Type::Integer(milliseconds) if *milliseconds >= 0 => {
+                            memory.clock = memory.clock.checked_add(*milliseconds).ok_or(MemoryError::ClockOverflow { clock: memory.clock, milliseconds: *milliseconds })?;
+                        },
+                        rest => return Err(OperationError::WrongType { expected: "non-negative Integer".to_string(), actual: format!("{rest}") }.into())
+                    },
+                    // stores the simulated clock in milliseconds into rax
+                    "time" => memory.set_register(Register::Rax, Type::Integer(memory.clock)),
+                    // stores the next line of the input into rax, Untyped at the end of the input
+                    "read" => {
+                        // a prompt printed before has to be visible while waiting for the input
+                        memory.output.flush()?;
+                        let line = memory.input.read_line().map_err(MemoryError::Input)?;
+                        memory.set_register(Register::Rax, line.map_or(Type::Untyped, Type::String));
+                    },
+                    // handled by the interpreter, since it owns the registered programs
+                    "spawn" => {},
+                    // handled by the interpreter, since it owns the environment
+                    "getenv" => {},
+                    // handled by the interpreter, since it owns the display settings
+                    "dumpstack" | "dumpregs" => {},
+                    // gives up control to the caller of `Interpreter::run_until_yield`, running the program continues
+                    "yield" => {},
+                    unknown => return Err(MemoryError::UnknownSyscall(unknown.to_string()))
                 }
             }
             Command::LoadEffectiveAddress(destination, source) => {
                 memory.set(destination, Type::Address(source.clone()))?;
             },
             Command::Compare(destination, value1, value2) => {
-                memory.set(destination, memory.get(value1)?.cmp(&memory.get(value2)?)?)?
+                let result = memory.get(value1)?.cmp(&memory.get(value2)?)?;
+                memory.flags.update(&result);
+                if let Some(destination) = destination {
+                    memory.set(destination, result)?
+                }
+            },
+            Command::SetEqual(destination) => memory.set(destination, memory.comparison_result(memory.flags.sign_value() == Some(0)))?,
+            Command::SetNotEqual(destination) => memory.set(destination, memory.comparison_result(memory.flags.sign_value().is_some_and(|value| value != 0)))?,
+            Command::SetLess(destination) => memory.set(destination, memory.comparison_result(memory.flags.sign_value() == Some(-1)))?,
+            Command::SetGreater(destination) => memory.set(destination, memory.comparison_result(memory.flags.sign_value() == Some(1)))?,
+            Command::MoveEqual(destination, source) if memory.flags.sign_value() == Some(0) => memory.set(destination, memory.get(source)?)?,
+            Command::MoveNotEqual(destination, source) if memory.flags.sign_value().is_some_and(|value| value != 0) => memory.set(destination, memory.get(source)?)?,
+            Command::MoveLess(destination, source) if memory.flags.sign_value() == Some(-1) => memory.set(destination, memory.get(source)?)?,
+            Command::MoveGreater(destination, source) if memory.flags.sign_value() == Some(1) => memory.set(destination, memory.get(source)?)?,
+            Command::MoveEqual(_, _) | Command::MoveNotEqual(_, _) | Command::MoveLess(_, _) | Command::MoveGreater(_, _) => {},
+            Command::ClearFlags => memory.flags = Flags::default(),
+            Command::RotateRegisters => {
+                let (rax, rbx, rcx) = (memory.register(Register::Rax), memory.register(Register::Rbx), memory.register(Register::Rcx));
+                memory.set_register(Register::Rax, rcx);
+                memory.set_register(Register::Rbx, rax);
+                memory.set_register(Register::Rcx, rbx);
+            },
+            Command::TypeOf(destination, source) => {
+                memory.set(destination, Type::Integer(memory.get(source)?.type_tag()))?
+            },
+            Command::StringEqual(destination, value1, value2) => {
+                memory.set(destination, memory.comparison_result(memory.get(value1)?.string_equal(&memory.get(value2)?)?))?
+            },
+            Command::LoadIndexed(destination, base, index) => {
+                memory.set(destination, memory.load_indexed(&memory.get(base)?, &memory.get(index)?)?)?
+            },
+            Command::AddressToInteger(destination, source) => match memory.get(source)? {
+                Type::Address(Address::StackPointer(index)) => memory.set(destination, Type::Integer(index as isize))?,
+                rest => return Err(OperationError::WrongType { expected: "stack Address".to_string(), actual: format!("{rest}") }.into())
             },
+            Command::IntegerToAddress(destination, source) => match memory.get(source)? {
+                Type::Integer(index) => {
+                    let length = memory.stack.len();
+                    let index = usize::try_from(index).ok()
+                        .filter(|i| *i < length)
+                        .ok_or(MemoryError::IndexOutOfBounds { index, length })?;
 
+                    memory.set(destination, Type::Address(Address::StackPointer(index)))?
+                },
+                rest => return Err(OperationError::WrongType { expected: "Integer".to_string(), actual: format!("{rest}") }.into())
+            },
+            Command::GetByte(destination, string, index) => {
+                let (string, index) = (memory.get(string)?, memory.get(index)?);
+                let Type::String(string) = string else {
+                    return Err(OperationError::WrongType { expected: "String".to_string(), actual: format!("{string}") }.into());
+                };
+
+                let Type::Integer(index) = index else {
+                    return Err(OperationError::WrongType { expected: "Integer".to_string(), actual: format!("{index}") }.into());
+                };
+
+                let byte = usize::try_from(index).ok()
+                    .and_then(|i| string.as_bytes().get(i))
+                    .ok_or(MemoryError::IndexOutOfBounds { index, length: string.len() })?;
+
+                memory.set(destination, Type::Integer(isize::from(*byte)))?
+            },
+            Command::ArrayNew(destination, length) => match memory.get(length)? {
+                Type::Integer(length) if length >= 0 => {
+                    if length as usize > ARRAY_LIMIT {
+                        return Err(MemoryError::OutOfMemory { requested: length as usize, available: ARRAY_LIMIT });
+                    }
+
+                    memory.set(destination, Type::Array(vec![Type::Untyped; length as usize]))?
+                },
+                rest => return Err(OperationError::WrongType { expected: "non-negative Integer".to_string(), actual: format!("{rest}") }.into())
+            },
+            Command::ArrayGet(destination, array, index) => {
+                let index = memory.get(index)?;
+                let mut array = memory.get(array)?;
+                let element = array_element(&mut array, &index)?.clone();
 
+                memory.set(destination, element)?
+            },
+            Command::ArraySet(array, index, value) => {
+                let index = memory.get(index)?;
+                let value = memory.get(value)?;
 
-            Command::Label(_) | Command::Return(_) | Command::Leave => {}
+                *array_element(memory.get_mut(array)?, &index)? = value;
+            },
+            Command::Data(destination, assignments) => {
+                for (offset, assignment) in assignments.iter().enumerate() {
+                    let slot = destination.try_add(&(offset as isize)).map_err(OperationError::from)?;
+                    memory.set(&slot, memory.get(assignment)?)?;
+                }
+            },
+            Command::Append(destination, source) => {
+                let suffix = memory.get(source)?.to_string_raw();
+
+                match memory.get_mut(destination)? {
+                    Type::String(value) if value.len() + suffix.len() > STRING_LIMIT => {
+                        return Err(MemoryError::OutOfMemory { requested: value.len() + suffix.len(), available: STRING_LIMIT })
+                    },
+                    Type::String(value) => value.push_str(&suffix),
+                    Type::Untyped if suffix.len() > STRING_LIMIT => return Err(MemoryError::OutOfMemory { requested: suffix.len(), available: STRING_LIMIT }),
+                    slot @ Type::Untyped => *slot = Type::String(suffix),
+                    rest => return Err(OperationError::WrongType { expected: "String".to_string(), actual: format!("{rest}") }.into())
+                }
+            },
+
+
+
+            Command::Label(_, _) | Command::Return(_) | Command::Leave | Command::Unwind(_) |
+            Command::StringData(_, _) | Command::HaltIf(_, _) | Command::SaveContext(_) |
+            Command::LoadContext(_) | Command::Nop => {}
         }
 
         Ok(())
     }
+
+    /// Returns the equivalent `mov`, if all operands of an arithmetic instruction like `add` are constant.
+    /// Identities like `add rax rbx 0` are folded by `fold_identity`
+    pub fn fold_constants(&self) -> Option<Command> {
+        let (destination, result) = match self {
+            Command::Add(destination, operand1, operand2) => (destination, operand1.as_const()?.add(&operand2.as_const()?)),
+            Command::Sub(destination, operand1, operand2) => (destination, operand1.as_const()?.sub(&operand2.as_const()?)),
+            Command::Mul(destination, operand1, operand2) => (destination, operand1.as_const()?.mul(&operand2.as_const()?)),
+            Command::Div(destination, operand1, operand2) => (destination, operand1.as_const()?.div(&operand2.as_const()?)),
+            Command::Mod(destination, operand1, operand2) => (destination, operand1.as_const()?.rem(&operand2.as_const()?)),
+            Command::And(destination, operand1, operand2) => (destination, operand1.as_const()?.and(&operand2.as_const()?)),
+            Command::Or(destination, operand1, operand2) => (destination, operand1.as_const()?.or(&operand2.as_const()?)),
+            Command::Xor(destination, operand1, operand2) => (destination, operand1.as_const()?.xor(&operand2.as_const()?)),
+            Command::Neg(destination, source) => (destination, source.as_const()?.neg()),
+            _ => return None
+        };
+
+        // failing operations are left as they are, so they still fail at runtime
+        Some(Command::Mov(destination.clone(), Assignment::Value(result.ok()?)))
+    }
+
+    /// Returns a `mov` of the other operand, if an arithmetic instruction adds or subtracts 0 or multiplies by 1, and
+    /// a `mov` of 0, if it multiplies by 0.
+    ///
+    /// The identities only hold for integers, adding 0 to a string appends it and multiplying a string by 0 empties it.
+    /// So the other operand has to be one of the registers, which are known to hold an integer
+    pub fn fold_identity(&self, integers: &HashSet<Register>) -> Option<Command> {
+        let constant = |assignment: &Assignment, value: isize| assignment.as_const() == Some(Type::Integer(value));
+        let integer = |assignment: &Assignment| match assignment {
+            Assignment::Address(Address::Register(register)) if integers.contains(register) => Some(assignment.clone()),
+            _ => None
+        };
+
+        let (destination, folded) = match self {
+            Command::Add(destination, operand1, operand2) if constant(operand2, 0) => (destination, integer(operand1)?),
+            Command::Add(destination, operand1, operand2) if constant(operand1, 0) => (destination, integer(operand2)?),
+            Command::Sub(destination, operand1, operand2) if constant(operand2, 0) => (destination, integer(operand1)?),
+            Command::Mul(destination, operand1, operand2) if constant(operand2, 1) => (destination, integer(operand1)?),
+            Command::Mul(destination, operand1, operand2) if constant(operand1, 1) => (destination, integer(operand2)?),
+            Command::Mul(destination, operand1, operand2) if constant(operand2, 0) => (destination, integer(operand1).map(|_| Assignment::Value(Type::Integer(0)))?),
+            Command::Mul(destination, operand1, operand2) if constant(operand1, 0) => (destination, integer(operand2).map(|_| Assignment::Value(Type::Integer(0)))?),
+            _ => return None
+        };
+
+        Some(Command::Mov(destination.clone(), folded))
+    }
+
+    /// Updates the registers, which are known to hold an integer after the command. Only `mov`, `add`, `sub` and `mul`
+    /// keep what is known. Every other instruction might jump, be jumped to or write registers, so nothing is known after it
+    pub fn track_integers(&self, integers: &mut HashSet<Register>) {
+        let integer = |assignment: &Assignment| match assignment {
+            Assignment::Value(value) => matches!(value, Type::Integer(_)),
+            Assignment::Address(Address::Register(register)) => integers.contains(register),
+            Assignment::Address(_) => false
+        };
+
+        let (destination, is_integer) = match self {
+            Command::Mov(Address::ProgramCounter, _) => return integers.clear(),
+            Command::Mov(destination, source) => (destination, integer(source)),
+            Command::Add(destination, operand1, operand2) |
+            Command::Sub(destination, operand1, operand2) |
+            Command::Mul(destination, operand1, operand2) => (destination, integer(operand1) && integer(operand2)),
+            _ => return integers.clear()
+        };
+
+        if let Address::Register(register) = destination {
+            if is_integer {
+                integers.insert(*register);
+            } else {
+                integers.remove(register);
+            }
+        }
+    }
 }
 
+/// Disassembles the command back into the syntax `Command::from_str` accepts
+impl Display for Command {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        fn conditional_jump(instruction: &str, assignment: &Option<Assignment>, jump_destination: &JumpDestination) -> String {
+            match assignment {
+                Some(assignment) => format!("{instruction} {} {jump_destination}", assignment.disassemble()),
+                None => format!("{instruction} {jump_destination}")
+            }
+        }
+
+        write!(f, "{}", match self {
+            Command::Mov(destination, assignment) => format!("mov {} {}", destination.disassemble(), assignment.disassemble()),
+            Command::Add(destination, operand1, operand2) => format!("add {} {} {}", destination.disassemble(), operand1.disassemble(), operand2.disassemble()),
+            Command::Sub(destination, operand1, operand2) => format!("sub {} {} {}", destination.disassemble(), operand1.disassemble(), operand2.disassemble()),
+            Command::Mul(destination, operand1, operand2) => format!("mul {} {} {}", destination.disassemble(), operand1.disassemble(), operand2.disassemble()),
+            Command::Div(destination, operand1, operand2) => format!("div {} {} {}", destination.disassemble(), operand1.disassemble(), operand2.disassemble()),
+            Command::Mod(destination, operand1, operand2) => format!("mod {} {} {}", destination.disassemble(), operand1.disassemble(), operand2.disassemble()),
+            Command::And(destination, operand1, operand2) => format!("and {} {} {}", destination.disassemble(), operand1.disassemble(), operand2.disassemble()),
+            Command::Or(destination, operand1, operand2) => format!("or {} {} {}", destination.disassemble(), operand1.disassemble(), operand2.disassemble()),
+            Command::Xor(destination, operand1, operand2) => format!("xor {} {} {}", destination.disassemble(), operand1.disassemble(), operand2.disassemble()),
+            Command::Neg(destination, source) => format!("neg {} {}", destination.disassemble(), source.disassemble()),
+            Command::Push(source) => format!("push {}", source.disassemble()),
+            Command::Pop(destination) => format!("pop {}", destination.disassemble()),
+            Command::LoadEffectiveAddress(destination, source) => format!("lea {} {}", destination.disassemble(), source.disassemble()),
+            Command::CallRet(destinations, jump_destination) => format!("call {} {jump_destination}", destinations.iter().map(|a| a.disassemble()).collect::<Vec<_>>().join(",")),
+            Command::CallVoid(jump_destination) => format!("call {jump_destination}"),
+            Command::Jmp(jump_destination) => format!("jmp {jump_destination}"),
+            Command::Label(label, Some(return_type)) => format!("{label} -> {return_type}:"),
+            Command::Label(label, None) => format!("{label}:"),
+            Command::Return(assignments) => format!("ret {}", assignments.iter().map(|a| a.disassemble()).collect::<Vec<_>>().join(" ")),
+            Command::Syscall(jump_destination, arguments) => {
+                let arguments = arguments.iter().map(|a| format!(" {}", a.disassemble())).collect::<String>();
+                format!("syscall {jump_destination}{arguments}")
+            },
+            Command::Leave => "leave".to_string(),
+            Command::JumpLess(assignment, jump_destination) => conditional_jump("jl", assignment, jump_destination),
+            Command::JumpGreater(assignment, jump_destination) => conditional_jump("jg", assignment, jump_destination),
+            Command::JumpLessEqual(assignment, jump_destination) => conditional_jump("jle", assignment, jump_destination),
+            Command::JumpGreaterEqual(assignment, jump_destination) => conditional_jump("jge", assignment, jump_destination),
+            Command::JumpNotEqual(assignment, jump_destination) => conditional_jump("jne", assignment, jump_destination),
+            Command::JumpEqual(assignment, jump_destination) => conditional_jump("je", assignment, jump_destination),
+            Command::Compare(Some(destination), operand1, operand2) => format!("cmp {} {} {}", destination.disassemble(), operand1.disassemble(), operand2.disassemble()),
+            Command::Compare(None, operand1, operand2) => format!("cmp {} {}", operand1.disassemble(), operand2.disassemble()),
+            Command::StringEqual(destination, operand1, operand2) => format!("streq {} {} {}", destination.disassemble(), operand1.disassemble(), operand2.disassemble()),
+            Command::TypeOf(destination, source) => format!("typeof {} {}", destination.disassemble(), source.disassemble()),
+            Command::JumpTable(index, jump_destinations) => format!("jmptable {} {}", index.disassemble(), jump_destinations.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(" ")),
+            Command::RotateRegisters => "rotregs".to_string(),
+            Command::SetEqual(destination) => format!("sete {}", destination.disassemble()),
+            Command::SetNotEqual(destination) => format!("setne {}", destination.disassemble()),
+            Command::SetLess(destination) => format!("setl {}", destination.disassemble()),
+            Command::SetGreater(destination) => format!("setg {}", destination.disassemble()),
+            Command::MoveEqual(destination, source) => format!("cmove {} {}", destination.disassemble(), source.disassemble()),
+            Command::MoveNotEqual(destination, source) => format!("cmovne {} {}", destination.disassemble(), source.disassemble()),
+            Command::MoveLess(destination, source) => format!("cmovl {} {}", destination.disassemble(), source.disassemble()),
+            Command::MoveGreater(destination, source) => format!("cmovg {} {}", destination.disassemble(), source.disassemble()),
+            Command::Nop => "nop".to_string(),
+            Command::Append(destination, source) => format!("append {} {}", destination.disassemble(), source.disassemble()),
+            Command::ArrayNew(destination, length) => format!("arrnew {} {}", destination.disassemble(), length.disassemble()),
+            Command::ArrayGet(destination, array, index) => format!("arrget {} {} {}", destination.disassemble(), array.disassemble(), index.disassemble()),
+            Command::ArraySet(array, index, value) => format!("arrset {} {} {}", array.disassemble(), index.disassemble(), value.disassemble()),
+            Command::Data(destination, assignments) => format!(".data {} {}", destination.disassemble(), assignments.iter().map(|a| a.disassemble()).collect::<Vec<_>>().join(" ")),
+            Command::Unwind(frames) => format!("unwind {frames}"),
+            Command::ClearFlags => "clrflags".to_string(),
+            Command::HaltIf(value, expected) => format!("haltif {} {}", value.disassemble(), expected.disassemble()),
+            Command::StringData(name, value) => format!(".string {name} {}", Type::String(value.clone()).disassemble()),
+            Command::LoadIndexed(destination, base, index) => format!("loadidx {} {} {}", destination.disassemble(), base.disassemble(), index.disassemble()),
+            Command::SaveContext(name) => format!("savectx {name}"),
+            Command::LoadContext(name) => format!("loadctx {name}"),
+            Command::AddressToInteger(destination, source) => format!("addr2int {} {}", destination.disassemble(), source.disassemble()),
+            Command::IntegerToAddress(destination, source) => format!("int2addr {} {}", destination.disassemble(), source.disassemble()),
+            Command::GetByte(destination, string, index) => format!("getbyte {} {} {}", destination.disassemble(), string.disassemble(), index.disassemble()),
+        })
+    }
+}
+
+/// Instruction names, which can't be used as labels
+const MNEMONICS: &[&str] = &[
+    "mov", "add", "sub", "lea", "call", "jmp", "ret", "syscall", "leave", "je", "jne", "jg", "jl", "cmp",
+    "sete", "setne", "setl", "setg", "streq", "typeof", "jmptable", "rotregs", "nop", "append", "loadidx",
+    "arrnew", "arrget", "arrset", "cmove", "cmovne", "cmovl", "cmovg", "unwind", "clrflags", "haltif",
+    "getbyte", "addr2int", "int2addr", "savectx", "loadctx", "mul", "div", "mod",
+    "and", "or", "xor", "neg", "push", "pop", "jle", "jge",
+];
+
 impl FromStr for Command {
     type Err = ParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let split = merge_quotes(s);
 
-        return if let [instruction] = &split[..] {
+        if let ["jmptable", index, jump_destinations @ ..] = &split[..] {
+            if jump_destinations.is_empty() {
+                return Err(ParseError::new("jmptable requires at least one label"));
+            }
+
+            Ok(Command::JumpTable(
+                Assignment::from_str(index)?,
+                jump_destinations.iter().map(|a| JumpDestination::from_str(a)).collect::<Result<Vec<_>, _>>()?
+            ))
+        } else if let [destination, "=", assignment] = &split[..] {
+            Ok(Command::Mov(destination_address(destination)?, Assignment::from_str(assignment)?))
+        } else if let [destination, "=", operand1, operator, operand2] = &split[..] {
+            match *operator {
+                "+" => Ok(Command::Add(destination_address(destination)?, Assignment::from_str(operand1)?, Assignment::from_str(operand2)?)),
+                "-" => Ok(Command::Sub(destination_address(destination)?, Assignment::from_str(operand1)?, Assignment::from_str(operand2)?)),
+                "*" => Ok(Command::Mul(destination_address(destination)?, Assignment::from_str(operand1)?, Assignment::from_str(operand2)?)),
+                "/" => Ok(Command::Div(destination_address(destination)?, Assignment::from_str(operand1)?, Assignment::from_str(operand2)?)),
+                "%" => Ok(Command::Mod(destination_address(destination)?, Assignment::from_str(operand1)?, Assignment::from_str(operand2)?)),
+                "&" => Ok(Command::And(destination_address(destination)?, Assignment::from_str(operand1)?, Assignment::from_str(operand2)?)),
+                "|" => Ok(Command::Or(destination_address(destination)?, Assignment::from_str(operand1)?, Assignment::from_str(operand2)?)),
+                "^" => Ok(Command::Xor(destination_address(destination)?, Assignment::from_str(operand1)?, Assignment::from_str(operand2)?)),
+                a => Err(ParseError::new(&format!("Unknown operator: {a}")))
+            }
+        } else if let [".string", name, value] = &split[..] {
+            if !matches!(Address::from_str(name)?, Address::Symbol(_)) {
+                return Err(ParseError::new(&format!("{name} is not a valid symbol")));
+            }
+
+            match Assignment::from_str(value)? {
+                Assignment::Value(Type::String(value)) => Ok(Command::StringData(name.to_string(), value)),
+                _ => Err(ParseError::new(&format!(".string requires a string, but got {value}")))
+            }
+        } else if let [".data", destination, assignments @ ..] = &split[..] {
+            if assignments.is_empty() {
+                return Err(ParseError::new(".data requires at least one value"));
+            }
+
+            Ok(Command::Data(
+                destination_address(destination)?,
+                assignments.iter().map(|a| Assignment::from_str(a)).collect::<Result<Vec<_>, _>>()?
+            ))
+        } else if let ["syscall", name, arguments @ ..] = &split[..] {
+            Ok(Command::Syscall(
+                JumpDestination::from_str(name)?,
+                arguments.iter().map(|a| Assignment::from_str(a)).collect::<Result<Vec<_>, _>>()?
+            ))
+        } else if let ["ret", assignments @ ..] = &split[..] {
+            if assignments.is_empty() {
+                return Err(ParseError::new("ret requires at least one assignment"));
+            }
+
+            Ok(Command::Return(assignments.iter().map(|a| Assignment::from_str(a)).collect::<Result<Vec<_>, _>>()?))
+        } else if let ["call", destination, destinations @ .., label_name] = &split[..] {
+            Ok(Command::CallRet(
+                [destination].into_iter().chain(destinations).map(|a| destination_address(a)).collect::<Result<Vec<_>, _>>()?,
+                JumpDestination::from_str(label_name)?
+            ))
+        } else if let [label_name, "->", return_type] = &split[..] {
+            let Some(return_type) = return_type.strip_suffix(':') else {
+                return Err(ParseError::new(&format!("The label {label_name} must end with ':' after its return type")));
+            };
+
+            label(label_name, Some(return_type))
+        } else if let [instruction] = &split[..] {
             match *instruction {
                 "leave" => Ok(Command::Leave),
-                label_name if label_name.ends_with(':') => Ok(Command::Label(label_name[0..label_name.chars().count() - 1].to_string())),
+                "nop" => Ok(Command::Nop),
+                "rotregs" => Ok(Command::RotateRegisters),
+                "clrflags" => Ok(Command::ClearFlags),
+                label_name if label_name.ends_with(':') => label(&label_name[..label_name.len() - 1], None),
                 a => Err(ParseError::new(&format!("Unknown instruction: {a}")))
             }
         } else if let [instruction, operand] = &split[..] {
             match *instruction {
-                "syscall" => Ok(Command::Syscall(JumpDestination::from_str(operand)?)),
+                "je" => Ok(Command::JumpEqual(None, JumpDestination::from_str(operand)?)),
+                "jne" => Ok(Command::JumpNotEqual(None, JumpDestination::from_str(operand)?)),
+                "jg" => Ok(Command::JumpGreater(None, JumpDestination::from_str(operand)?)),
+                "jl" => Ok(Command::JumpLess(None, JumpDestination::from_str(operand)?)),
+                "jle" => Ok(Command::JumpLessEqual(None, JumpDestination::from_str(operand)?)),
+                "jge" => Ok(Command::JumpGreaterEqual(None, JumpDestination::from_str(operand)?)),
+                "sete" => Ok(Command::SetEqual(destination_address(operand)?)),
+                "setne" => Ok(Command::SetNotEqual(destination_address(operand)?)),
+                "setl" => Ok(Command::SetLess(destination_address(operand)?)),
+                "setg" => Ok(Command::SetGreater(destination_address(operand)?)),
                 "jmp" => Ok(Command::Jmp(JumpDestination::from_str(operand)?)),
-                "ret" => Ok(Command::Return(Assignment::from_str(operand)?)),
                 "call" => Ok(Command::CallVoid(JumpDestination::from_str(operand)?)),
+                "unwind" => match operand.parse::<usize>()? {
+                    0 => Err(ParseError::new("unwind requires at least one stack frame")),
+                    frames => Ok(Command::Unwind(frames))
+                },
+                "push" => Ok(Command::Push(Assignment::from_str(operand)?)),
+                "pop" => Ok(Command::Pop(destination_address(operand)?)),
+                "savectx" => Ok(Command::SaveContext(operand.to_string())),
+                "loadctx" => Ok(Command::LoadContext(operand.to_string())),
                 a => Err(ParseError::new(&format!("Unknown instruction: {a}")))
             }
         }
         else if let [instruction, destination, assignment] = &split[..] {
             match *instruction {
-                "je" => Ok(Command::JumpEqual(Assignment::from_str(destination)?, JumpDestination::from_str(assignment)?)),
-                "jne" => Ok(Command::JumpNotEqual(Assignment::from_str(destination)?, JumpDestination::from_str(assignment)?)),
-                "jg" => Ok(Command::JumpGreater(Assignment::from_str(destination)?, JumpDestination::from_str(assignment)?)),
-                "jl" => Ok(Command::JumpLess(Assignment::from_str(destination)?, JumpDestination::from_str(assignment)?)),
-
-                "lea" => Ok(Command::LoadEffectiveAddress(Address::from_str(destination)?, Address::from_str(assignment)?)),
-                "mov" => Ok(Command::Mov(Address::from_str(destination)?, Assignment::from_str(assignment)?)),
-                "call" => Ok(Command::CallRet(Address::from_str(destination)?, JumpDestination::from_str(assignment)?)),
+                "je" => Ok(Command::JumpEqual(Some(Assignment::from_str(destination)?), JumpDestination::from_str(assignment)?)),
+                "jne" => Ok(Command::JumpNotEqual(Some(Assignment::from_str(destination)?), JumpDestination::from_str(assignment)?)),
+                "jg" => Ok(Command::JumpGreater(Some(Assignment::from_str(destination)?), JumpDestination::from_str(assignment)?)),
+                "jl" => Ok(Command::JumpLess(Some(Assignment::from_str(destination)?), JumpDestination::from_str(assignment)?)),
+                "jle" => Ok(Command::JumpLessEqual(Some(Assignment::from_str(destination)?), JumpDestination::from_str(assignment)?)),
+                "jge" => Ok(Command::JumpGreaterEqual(Some(Assignment::from_str(destination)?), JumpDestination::from_str(assignment)?)),
+
+                "lea" => Ok(Command::LoadEffectiveAddress(destination_address(destination)?, Address::from_str(assignment)?)),
+                "mov" => Ok(Command::Mov(destination_address(destination)?, Assignment::from_str(assignment)?)),
+                "cmove" => Ok(Command::MoveEqual(destination_address(destination)?, Assignment::from_str(assignment)?)),
+                "cmovne" => Ok(Command::MoveNotEqual(destination_address(destination)?, Assignment::from_str(assignment)?)),
+                "cmovl" => Ok(Command::MoveLess(destination_address(destination)?, Assignment::from_str(assignment)?)),
+                "cmovg" => Ok(Command::MoveGreater(destination_address(destination)?, Assignment::from_str(assignment)?)),
+                "haltif" => Ok(Command::HaltIf(Assignment::from_str(destination)?, Assignment::from_str(assignment)?)),
+                "typeof" => Ok(Command::TypeOf(destination_address(destination)?, Assignment::from_str(assignment)?)),
+                "arrnew" => Ok(Command::ArrayNew(destination_address(destination)?, Assignment::from_str(assignment)?)),
+                "append" => Ok(Command::Append(destination_address(destination)?, Assignment::from_str(assignment)?)),
+                "addr2int" => Ok(Command::AddressToInteger(destination_address(destination)?, Assignment::from_str(assignment)?)),
+                "int2addr" => Ok(Command::IntegerToAddress(destination_address(destination)?, Assignment::from_str(assignment)?)),
+                "neg" => Ok(Command::Neg(destination_address(destination)?, Assignment::from_str(assignment)?)),
+                "cmp" => Ok(Command::Compare(None, Assignment::from_str(destination)?, Assignment::from_str(assignment)?)),
                 a => Err(ParseError::new(&format!("Unknown instruction: {a}")))
             }
         } else if let [instruction, destination, operand1, operand2] = &split[..] {
             match *instruction {
-                "cmp" => Ok(Command::Compare(Address::from_str(destination)?, Assignment::from_str(operand1)?, Assignment::from_str(operand2)?)),
-                "add" => Ok(Command::Add(Address::from_str(destination)?, Assignment::from_str(operand1)?, Assignment::from_str(operand2)?)),
-                "sub" => Ok(Command::Sub(Address::from_str(destination)?, Assignment::from_str(operand1)?, Assignment::from_str(operand2)?)),
+                "cmp" => Ok(Command::Compare(Some(destination_address(destination)?), Assignment::from_str(operand1)?, Assignment::from_str(operand2)?)),
+                "add" => Ok(Command::Add(destination_address(destination)?, Assignment::from_str(operand1)?, Assignment::from_str(operand2)?)),
+                "sub" => Ok(Command::Sub(destination_address(destination)?, Assignment::from_str(operand1)?, Assignment::from_str(operand2)?)),
+                "mul" => Ok(Command::Mul(destination_address(destination)?, Assignment::from_str(operand1)?, Assignment::from_str(operand2)?)),
+                "div" => Ok(Command::Div(destination_address(destination)?, Assignment::from_str(operand1)?, Assignment::from_str(operand2)?)),
+                "mod" => Ok(Command::Mod(destination_address(destination)?, Assignment::from_str(operand1)?, Assignment::from_str(operand2)?)),
+                "and" => Ok(Command::And(destination_address(destination)?, Assignment::from_str(operand1)?, Assignment::from_str(operand2)?)),
+                "or" => Ok(Command::Or(destination_address(destination)?, Assignment::from_str(operand1)?, Assignment::from_str(operand2)?)),
+                "xor" => Ok(Command::Xor(destination_address(destination)?, Assignment::from_str(operand1)?, Assignment::from_str(operand2)?)),
+                "streq" => Ok(Command::StringEqual(destination_address(destination)?, Assignment::from_str(operand1)?, Assignment::from_str(operand2)?)),
+                "arrget" => Ok(Command::ArrayGet(destination_address(destination)?, Assignment::from_str(operand1)?, Assignment::from_str(operand2)?)),
+                "arrset" => Ok(Command::ArraySet(destination_address(destination)?, Assignment::from_str(operand1)?, Assignment::from_str(operand2)?)),
+                "loadidx" => Ok(Command::LoadIndexed(destination_address(destination)?, Assignment::from_str(operand1)?, Assignment::from_str(operand2)?)),
+                "getbyte" => Ok(Command::GetByte(destination_address(destination)?, Assignment::from_str(operand1)?, Assignment::from_str(operand2)?)),
                 a => Err(ParseError::new(&format!("Unknown instruction: {a}")))
             }
         } else {
@@ -175,33 +829,107 @@ impl FromStr for Command {
     }
 }
 
+/// Stores the result of the operation on both operands in the destination and updates the flags, if `flag_arithmetic` is enabled
+fn arithmetic(memory: &mut Memory, destination: &Address, operand1: &Assignment, operand2: &Assignment, operation: fn(&Type, &Type) -> Result<Type, OperationError>) -> Result<(), MemoryError> {
+    let result = operation(&memory.get(operand1)?, &memory.get(operand2)?).map_err(|error| operand_error(error, &[operand1, operand2]))?;
+    if memory.flag_arithmetic {
+        memory.flags.update(&result);
+    }
+
+    memory.set(destination, result)
+}
+
+/// Attaches the operands as written in the source code, so it's visible, if a value came from an immediate, a register or a pointer
+fn operand_error(error: OperationError, operands: &[&Assignment]) -> MemoryError {
+    MemoryError::OperandError { error, operands: operands.iter().map(|a| (*a).clone()).collect() }
+}
+
+/// Returns the element at the index, if the value is an array
+fn array_element<'a>(array: &'a mut Type, index: &Type) -> Result<&'a mut Type, MemoryError> {
+    let Type::Array(elements) = array else {
+        return Err(OperationError::WrongType { expected: "Array".to_string(), actual: format!("{array}") }.into());
+    };
+
+    let Type::Integer(index) = *index else {
+        return Err(OperationError::WrongType { expected: "Integer".to_string(), actual: format!("{index}") }.into());
+    };
+
+    let length = elements.len();
+    usize::try_from(index).ok()
+        .and_then(|i| elements.get_mut(i))
+        .ok_or(MemoryError::IndexOutOfBounds { index, length })
+}
+
+fn label(label_name: &str, return_type: Option<&str>) -> Result<Command, ParseError> {
+    if MNEMONICS.contains(&label_name) {
+        return Err(ParseError::new(&format!("The instruction {label_name} can't be used as a label")));
+    }
+
+    if let Some(return_type) = return_type.filter(|return_type| !TYPE_NAMES.contains(return_type)) {
+        return Err(ParseError::new(&format!("Unknown return type {return_type}, expected one of: {}", TYPE_NAMES.join(", "))));
+    }
+
+    Ok(Command::Label(label_name.to_string(), return_type.map(str::to_string)))
+}
+
+/// Parses the address an instruction writes into
+fn destination_address(s: &str) -> Result<Address, ParseError> {
+    if s.starts_with('"') {
+        return Err(ParseError::new(&format!("A string literal cannot be a destination: {s}")));
+    }
+
+    Address::from_str(s)
+}
+
 fn merge_quotes(target: &str) -> Vec<&str> {
     let mut result = vec![];
     let mut word_range = 0..0;
-    let mut open_bracket = false;
+    // the quote character of the currently open string or character literal
+    let mut open_quote = None;
+    // the previous character was a backslash inside of a literal, so the current one doesn't close it
+    let mut escaped = false;
+    // whitespace inside of brackets doesn't separate, so `[ rax ]` stays a single operand
+    let mut bracket_depth: usize = 0;
 
     for char in target.chars() {
         match char {
-            ';' => {
+            _ if escaped => {
+                escaped = false;
+                word_range.end += char.len_utf8();
+            },
+            '\\' if open_quote.is_some() => {
+                escaped = true;
+                word_range.end += 1;
+            },
+            ';' if open_quote.is_none() => {
                 break;
             },
-            ' ' if !open_bracket => {
+            // operands may be separated by commas as well, like `mov rax, 5`
+            separator if (separator.is_whitespace() || separator == ',') && open_quote.is_none() && bracket_depth == 0 => {
                 let word = &target[word_range.clone()];
                 if !word.is_empty() {
                     result.push(word);
                 }
-                word_range.start = word_range.end + 1;
+                word_range.start = word_range.end + separator.len_utf8();
+                word_range.end += separator.len_utf8();
+            },
+            '[' if open_quote.is_none() => {
+                bracket_depth += 1;
                 word_range.end += 1;
             },
-            '"' if !open_bracket => {
-                open_bracket = true;
+            ']' if open_quote.is_none() => {
+                bracket_depth = bracket_depth.saturating_sub(1);
                 word_range.end += 1;
             },
-            '"' if open_bracket => {
-                open_bracket = false;
+            '"' | '\'' if open_quote.is_none() => {
+                open_quote = Some(char);
+                word_range.end += 1;
+            },
+            quote if open_quote == Some(quote) => {
+                open_quote = None;
                 word_range.end += 1;
             }
-            _ => { word_range.end += 1; }
+            _ => { word_range.end += char.len_utf8(); }
         }
     }
 
@@ -210,4 +938,735 @@ fn merge_quotes(target: &str) -> Vec<&str> {
     }
 
     result
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use crate::interpreter::Interpreter;
+    use crate::program_error::ProgramError;
+    use crate::test_utils::{captured, run, text};
+    use super::*;
+
+    /// Returns 7, if the conditional jump in the line after the setup branches, 1 otherwise
+    fn branches(setup: &str, jump: &str) -> Type {
+        run(&format!("mov rbx 1\n{setup}\n{jump} hit\nret rbx\nhit:\nmov rbx 7\nleave")).unwrap()
+    }
+
+    #[test]
+    fn flag_arithmetic_lets_je_branch_on_a_zero_result() {
+        let mut interpreter = Interpreter::from_str("mov rbx 1\nmov rax 5\nsub rax rax rax\nje zero\nret rbx\nzero:\nmov rbx 7\nleave").unwrap();
+        interpreter.memory.flag_arithmetic = true;
+        assert_eq!(interpreter.run().unwrap(), Type::Integer(7));
+
+        let mut interpreter = Interpreter::from_str("mov rbx 1\nmov rax 5\nsub rax rax 3\nje zero\nret rbx\nzero:\nmov rbx 7\nleave").unwrap();
+        interpreter.memory.flag_arithmetic = true;
+        assert_eq!(interpreter.run().unwrap(), Type::Integer(1));
+    }
+
+    #[test]
+    fn arithmetic_leaves_the_flags_untouched_by_default() {
+        assert_eq!(branches("cmp 1 2\nmov rax 5\nsub rax rax rax", "je"), Type::Integer(1));
+        assert_eq!(branches("cmp 1 2\nmov rax 5\nsub rax rax rax", "jl"), Type::Integer(7));
+    }
+
+    #[test]
+    fn printi_and_prints_write_their_operand_with_a_newline() {
+        let (mut interpreter, output) = captured("mov rax 42\nsyscall printi\nmov rax \"hi there\"\nsyscall prints\nret 0");
+        interpreter.run().unwrap();
+
+        assert_eq!(text(&output), "42\nhi there\n");
+    }
+
+    #[test]
+    fn printi_rejects_a_string() {
+        let (mut interpreter, output) = captured("mov rax \"x\"\nsyscall printi\nret 0");
+
+        match interpreter.run() {
+            Err(ProgramError::Runtime { instruction: 1, error, .. }) => assert!(matches!(*error, ProgramError::Memory(MemoryError::OperationError(OperationError::WrongType { .. })))),
+            other => panic!("expected a wrong type, got {other:?}")
+        }
+        assert_eq!(text(&output), "");
+    }
+
+    #[test]
+    fn recursion_stops_itself_at_a_depth() {
+        assert_eq!(run("syscall depth\nret rax").unwrap(), Type::Integer(0));
+
+        let source = "call rcx recurse\nret rcx\nrecurse:\n    syscall depth\n    mov rcx rax\n    cmp rbx rax 7\n    jl rbx again\n    ret rcx\nagain:\n    call rcx recurse\n    leave\n";
+        assert_eq!(run(source).unwrap(), Type::Integer(7));
+    }
+
+    #[test]
+    fn unknown_syscall_fails() {
+        assert_eq!(run("syscall nonexistent\nret 0").unwrap_err().to_string(), "at instruction 0 (line 1): Unknown syscall: nonexistent");
+    }
+
+    #[test]
+    fn streq_compares_strings() {
+        let mut interpreter = Interpreter::from_str("mov rax \"a b\"\nstreq rbx rax \"a b\"\nstreq rcx rax \"x\"\nret 0").unwrap();
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.memory.register(Register::Rbx), Type::Integer(1));
+        assert_eq!(interpreter.memory.register(Register::Rcx), Type::Integer(0));
+    }
+
+    #[test]
+    fn streq_rejects_a_non_string() {
+        match run("streq rbx \"a\" 5\nret 0") {
+            Err(ProgramError::Runtime { instruction: 0, error, .. }) => assert!(matches!(*error, ProgramError::Memory(MemoryError::OperationError(OperationError::WrongType { .. })))),
+            other => panic!("expected a wrong type, got {other:?}")
+        }
+    }
+
+    #[test]
+    fn typeof_stores_the_type_tag() {
+        let mut interpreter = Interpreter::from_str("typeof rax \"hi\"\ntypeof rbx 5\ntypeof rcx rdx\nret 0").unwrap();
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.memory.register(Register::Rax), Type::Integer(2));
+        assert_eq!(interpreter.memory.register(Register::Rbx), Type::Integer(1));
+        assert_eq!(interpreter.memory.register(Register::Rcx), Type::Integer(0));
+    }
+
+    #[test]
+    fn rotregs_shifts_rax_into_rbx_into_rcx() {
+        let mut interpreter = Interpreter::from_str("mov rax 1\nmov rbx 2\nmov rcx 3\nrotregs\nret 0").unwrap();
+        interpreter.run().unwrap();
+
+        let registers = [Register::Rax, Register::Rbx, Register::Rcx].map(|register| interpreter.memory.register(register));
+        assert_eq!(registers, [Type::Integer(3), Type::Integer(1), Type::Integer(2)]);
+    }
+
+    #[test]
+    fn set_instructions_store_the_comparison_result() {
+        for (value, expected) in [(5, [1, 0, 0]), (4, [0, 1, 0]), (6, [0, 0, 1])] {
+            let mut interpreter = Interpreter::from_str(&format!("mov rax {value}\ncmp rax 5\nsete rbx\nsetl rcx\nsetg rdx\nret 0")).unwrap();
+            interpreter.run().unwrap();
+
+            let results = [Register::Rbx, Register::Rcx, Register::Rdx].map(|register| interpreter.memory.register(register));
+            assert_eq!(results, expected.map(Type::Integer));
+        }
+    }
+
+    #[test]
+    fn tabs_and_repeated_whitespace_separate_operands() {
+        let expected = Command::from_str("mov rax 5").unwrap();
+
+        for line in ["mov\trax\t5", "mov  rax \t 5", "\tmov rax    5", "mov\u{00a0}rax 5"] {
+            assert_eq!(Command::from_str(line).unwrap(), expected, "{line:?}");
+        }
+    }
+
+    #[test]
+    fn whitespace_inside_quotes_is_kept() {
+        assert_eq!(Command::from_str("mov\trax \"a\tb  c\"").unwrap(), Command::from_str("mov rax \"a\tb  c\"").unwrap());
+        assert_eq!(merge_quotes("mov rax \"a\tb  c\""), vec!["mov", "rax", "\"a\tb  c\""]);
+    }
+
+    #[test]
+    fn append_concatenates_onto_the_destination() {
+        let source = "mov rax \"#\"\nmov rcx 0\nloop:\nappend rax rcx\nadd rcx rcx 1\ncmp rcx 3\njl loop\nret rax";
+        assert_eq!(run(source).unwrap(), Type::String("#012".to_string()));
+        assert_eq!(run("append rax 'a'\nappend rax \"bc\"\nret rax").unwrap(), Type::String("abc".to_string()));
+    }
+
+    #[test]
+    fn append_respects_the_length_limit() {
+        let source = format!("mov rax \"a\"\nmul rax rax {}\nappend rax 'b'\nappend rax 'c'\nret rax", STRING_LIMIT - 1);
+        match run(&source) {
+            Err(ProgramError::Runtime { instruction, error, .. }) => {
+                assert_eq!(instruction, 3);
+                assert!(matches!(*error, ProgramError::Memory(MemoryError::OutOfMemory { requested, available: STRING_LIMIT }) if requested == STRING_LIMIT + 1));
+            },
+            other => panic!("expected out of memory, got {other:?}")
+        }
+    }
+
+    #[test]
+    fn append_rejects_a_non_string_destination() {
+        match run("mov rax 3\nappend rax 1\nret rax") {
+            Err(ProgramError::Runtime { instruction: 1, error, .. }) => assert!(matches!(*error, ProgramError::Memory(MemoryError::OperationError(OperationError::WrongType { .. })))),
+            other => panic!("expected a wrong type, got {other:?}")
+        }
+    }
+
+    #[test]
+    fn labels_ignore_indentation_and_trailing_comments() {
+        for line in ["loop:", "  loop:  ", "\tloop:", "loop: ; start", "    loop:   ; start"] {
+            assert_eq!(Command::from_str(line).unwrap(), Command::Label("loop".to_string(), None), "{line:?}");
+        }
+    }
+
+    #[test]
+    fn indented_labels_are_found_when_running() {
+        let interpreter = Interpreter::from_str("  \n\tcall f  \n  ret rax\n   \n    f:   ; start\n  mov rax 9\n  \t\nleave").unwrap();
+        assert!(interpreter.source_code.contains(&Command::Label("f".to_string(), None)));
+
+        assert_eq!(run("\tcall rax f\n  ret rax\n    f:   ; start\n  ret 9").unwrap(), Type::Integer(9));
+    }
+
+    #[test]
+    fn arrays_are_created_read_and_written() {
+        let mut interpreter = Interpreter::from_str("arrnew sp[0] 3\narrset sp[0] 1 42\narrset sp[0] 0 \"x\"\narrget rax sp[0] 1\nmov rbx sp[0]\nret rax").unwrap();
+
+        assert_eq!(interpreter.run().unwrap(), Type::Integer(42));
+        assert_eq!(interpreter.memory.register(Register::Rbx), Type::Array(vec![Type::String("x".to_string()), Type::Integer(42), Type::Untyped]));
+        assert_eq!(interpreter.memory.register(Register::Rbx).to_string_raw(), "[x, 42, ]");
+    }
+
+    #[test]
+    fn array_access_out_of_range_fails() {
+        let error = run("arrnew rax 2\narrget rbx rax 2\nret rbx").unwrap_err().to_string();
+        assert!(error.ends_with("Index 2 is out of bounds for length 2"), "{error}");
+
+        let error = run("arrnew rax 2\narrset rax -1 1\nret rbx").unwrap_err().to_string();
+        assert!(error.ends_with("Index -1 is out of bounds for length 2"), "{error}");
+    }
+
+    #[test]
+    fn huge_array_fails() {
+        match run("arrnew rax 9223372036854775807\nret 0") {
+            Err(ProgramError::Runtime { error, .. }) => assert!(matches!(*error, ProgramError::Memory(MemoryError::OutOfMemory { available: ARRAY_LIMIT, .. }))),
+            other => panic!("expected an allocation failure, got {other:?}")
+        }
+    }
+
+    #[test]
+    fn mnemonics_cannot_be_labels() {
+        for label in ["mov:", "jmp:", "syscall:"] {
+            let error = Command::from_str(label).unwrap_err().to_string();
+            assert!(error.contains(&format!("The instruction {} can't be used as a label", label.trim_end_matches(':'))), "{error}");
+        }
+
+        assert_eq!(Command::from_str("move:").unwrap(), Command::Label("move".to_string(), None));
+    }
+
+    #[test]
+    fn cmovl_moves_only_if_less() {
+        for (a, b, expected) in [(1, 5, 1), (7, 5, 0), (5, 5, 0)] {
+            assert_eq!(run(&format!("mov rax {a}\nmov rbx {b}\nmov rcx 0\ncmp rax rbx\ncmovl rcx rax\nret rcx")).unwrap(), Type::Integer(expected));
+        }
+    }
+
+    #[test]
+    fn cmov_computes_a_branchless_minimum() {
+        for (a, b) in [(1, 5), (7, 5), (5, 5)] {
+            assert_eq!(run(&format!("mov rax {a}\nmov rbx {b}\nmov rcx rbx\ncmp rax rbx\ncmovl rcx rax\nret rcx")).unwrap(), Type::Integer(a.min(b)));
+        }
+    }
+
+    #[test]
+    fn data_directive_fills_consecutive_slots() {
+        let mut interpreter = Interpreter::from_str(".data sp[2] 1 2 3 4\nret sp[5]").unwrap();
+        assert_eq!(interpreter.source_code[0].to_string(), ".data sp[2] 1 2 3 4");
+
+        assert_eq!(interpreter.run().unwrap(), Type::Integer(4));
+        assert_eq!(&interpreter.memory.stack[1..7], &[Type::Untyped, Type::Integer(1), Type::Integer(2), Type::Integer(3), Type::Integer(4), Type::Untyped]);
+    }
+
+    #[test]
+    fn data_directive_past_the_stack_fails() {
+        assert!(run(".data sp[62] 1 2 3\nret 0").is_err());
+    }
+
+    #[test]
+    fn printf_takes_inline_arguments() {
+        let (mut interpreter, output) = captured("mov rbx 3\nsyscall printf \"hello {} and {}\" rbx 'x'\nsyscall printi 42\nmov rax \"{}!\"\nmov rbx 1\nsyscall printf\nret 0");
+        assert_eq!(interpreter.source_code[1].to_string(), "syscall printf \"hello {} and {}\" rbx 'x'");
+
+        interpreter.run().unwrap();
+        assert_eq!(text(&output), "hello 3 and x\n42\n1!\n");
+    }
+
+    #[test]
+    fn identities_fold_for_registers_holding_an_integer() {
+        let integers = HashSet::from([Register::Rbx]);
+        let fold = |line: &str| Command::from_str(line).unwrap().fold_identity(&integers).map(|command| command.to_string());
+
+        assert_eq!(fold("add rax rbx 0").as_deref(), Some("mov rax rbx"));
+        assert_eq!(fold("add rax 0 rbx").as_deref(), Some("mov rax rbx"));
+        assert_eq!(fold("sub rax rbx 0").as_deref(), Some("mov rax rbx"));
+        assert_eq!(fold("mul rax 1 rbx").as_deref(), Some("mov rax rbx"));
+        assert_eq!(fold("mul rax rbx 0").as_deref(), Some("mov rax 0"));
+        assert_eq!(fold("sub rax 0 rbx"), None);
+        assert_eq!(fold("add rax rcx 0"), None);
+        assert_eq!(fold("add rax sp[0] 0"), None);
+    }
+
+    #[test]
+    fn integer_registers_are_forgotten_after_other_instructions() {
+        let mut integers = HashSet::new();
+        for line in ["mov rax 5", "add rbx rax 2", "mov rcx \"s\""] {
+            Command::from_str(line).unwrap().track_integers(&mut integers);
+        }
+        assert_eq!(integers, HashSet::from([Register::Rax, Register::Rbx]));
+
+        Command::from_str("mov rax rcx").unwrap().track_integers(&mut integers);
+        assert_eq!(integers, HashSet::from([Register::Rbx]));
+
+        Command::from_str("loop:").unwrap().track_integers(&mut integers);
+        assert!(integers.is_empty());
+    }
+
+    #[test]
+    fn infix_assignments_parse_like_mnemonics() {
+        assert_eq!(Command::from_str("rax = 5").unwrap(), Command::from_str("mov rax 5").unwrap());
+        assert_eq!(Command::from_str("rax = rbx + 1").unwrap(), Command::from_str("add rax rbx 1").unwrap());
+        assert_eq!(Command::from_str("sp[0] = rax - -2").unwrap(), Command::from_str("sub sp[0] rax -2").unwrap());
+        assert!(Command::from_str("rax = rbx ? 2").is_err());
+    }
+
+    #[test]
+    fn hash_syscall_stores_the_hash_of_rax_in_rbx() {
+        let mut interpreter = Interpreter::from_str("syscall hash \"abc\"\nmov rcx rbx\nsyscall hash \"abc\"\nret rcx").unwrap();
+
+        assert_eq!(interpreter.run().unwrap(), Type::Integer(Type::String("abc".to_string()).stable_hash().unwrap()));
+        assert_eq!(interpreter.memory.register(Register::Rbx), interpreter.memory.register(Register::Rcx));
+        assert!(run("syscall hash rax\nret 0").is_err());
+    }
+
+    #[test]
+    fn cleared_flags_never_branch() {
+        for jump in ["je", "jne", "jl", "jg", "jle", "jge"] {
+            assert_eq!(branches("cmp rbx 0\nclrflags", jump), Type::Integer(1), "{jump}");
+            assert_eq!(branches("cmp rbx 1\nclrflags", jump), Type::Integer(1), "{jump}");
+            assert_eq!(branches("nop", jump), Type::Integer(1), "{jump}");
+        }
+
+        assert_eq!(branches("cmp 1 1\nclrflags\ncmp 1 1", "je"), Type::Integer(7));
+    }
+
+    #[test]
+    fn cleared_flags_neither_set_nor_move() {
+        for instruction in ["sete", "setne", "setl", "setg"] {
+            assert_eq!(run(&format!("cmp 1 2\nclrflags\n{instruction} rax\nret rax")).unwrap(), Type::Integer(0), "{instruction}");
+            assert_eq!(run(&format!("{instruction} rax\nret rax")).unwrap(), Type::Integer(0), "{instruction}");
+        }
+
+        for instruction in ["cmove", "cmovne", "cmovl", "cmovg"] {
+            assert_eq!(run(&format!("mov rax 0\n{instruction} rax 5\nret rax")).unwrap(), Type::Integer(0), "{instruction}");
+        }
+    }
+
+    #[test]
+    fn addresses_include_destinations_and_read_addresses() {
+        let command = Command::from_str("add sp[0] rax 5").unwrap();
+        assert_eq!(command.addresses(), vec![&Address::StackPointer(0), &Address::Register(Register::Rax)]);
+
+        let command = Command::from_str("syscall printf msg rbx").unwrap();
+        assert_eq!(command.addresses(), vec![&Address::Symbol("msg".to_string()), &Address::Register(Register::Rbx)]);
+    }
+
+    #[test]
+    fn failing_arithmetic_names_its_operands() {
+        match run("mov rbx \"hi\"\nmov rcx 5\nsub rax rcx rbx\nret rax") {
+            Err(ProgramError::Runtime { instruction: 2, error, .. }) => {
+                assert!(matches!(&*error, ProgramError::Memory(MemoryError::OperandError { operands, .. })
+                    if operands == &vec![Assignment::Address(Address::Register(Register::Rcx)), Assignment::Address(Address::Register(Register::Rbx))]));
+                assert!(error.to_string().contains("(rcx, rbx)"), "{error}");
+            },
+            other => panic!("expected an operand error, got {other:?}")
+        }
+
+        match run("sub rax 1 true\nret rax") {
+            Err(ProgramError::Runtime { error, .. }) => assert!(error.to_string().contains("(1, true)"), "{error}"),
+            other => panic!("expected an operand error, got {other:?}")
+        }
+    }
+
+    #[test]
+    fn backtrace_describes_nested_calls() {
+        let backtrace = run("call rcx outer\nret rcx\nouter:\n    call rcx inner\n    mov rbx 1\n    ret rcx\ninner:\n    syscall backtrace\n    ret rax").unwrap();
+        assert_eq!(backtrace, Type::String("call at 3 <- call at 0".to_string()));
+    }
+
+    #[test]
+    fn strict_printf_rejects_mismatched_placeholders() {
+        let strict = |source: &str| {
+            let (mut interpreter, output) = captured(source);
+            interpreter.memory.strict_printf = true;
+            (interpreter.run(), text(&output))
+        };
+
+        match strict("mov rax \"no placeholder\"\nmov rbx 5\nsyscall printf\nret 0").0 {
+            Err(ProgramError::Runtime { instruction: 2, error, .. }) => assert!(matches!(*error, ProgramError::Memory(MemoryError::FormatMismatch { placeholders: 0, arguments: 1 }))),
+            other => panic!("expected a format mismatch, got {other:?}")
+        }
+        match strict("syscall printf \"{} + {}\" 1\nret 0").0 {
+            Err(ProgramError::Runtime { instruction: 0, error, .. }) => assert!(matches!(*error, ProgramError::Memory(MemoryError::FormatMismatch { placeholders: 2, arguments: 1 }))),
+            other => panic!("expected a format mismatch, got {other:?}")
+        }
+
+        let (result, output) = strict("syscall printf \"{} + {}\" 1 2\nmov rax \"plain\"\nsyscall printf\nret 0");
+        assert_eq!(result.unwrap(), Type::Integer(0));
+        assert_eq!(output, "1 + 2\nplain\n");
+
+        let (mut interpreter, output) = captured("mov rax \"no placeholder\"\nmov rbx 5\nsyscall printf\nret 0");
+        assert_eq!(interpreter.run().unwrap(), Type::Integer(0));
+        assert_eq!(text(&output), "no placeholder\n");
+    }
+
+    #[test]
+    fn every_type_survives_a_spill_and_reload() {
+        let cases = [
+            ("mov rax \"spilled\"", Type::String("spilled".to_string())),
+            ("mov rax -5", Type::Integer(-5)),
+            ("mov rax 2.5", Type::Float(2.5)),
+            ("mov rax true", Type::Boolean(true)),
+            ("mov rax 'c'", Type::Char('c')),
+            ("lea rax sp[3]", Type::Address(Address::StackPointer(3))),
+            ("arrnew rax 2\narrset rax 1 'x'", Type::Array(vec![Type::Untyped, Type::Char('x')])),
+            ("nop", Type::Untyped),
+        ];
+
+        for (setup, expected) in cases {
+            let source = format!("{setup}\nmov sp[0] rax\nmov rax 0\nmov rbx sp[0]\nret rbx");
+            assert_eq!(run(&source).unwrap(), expected, "{setup}");
+        }
+
+        // a reloaded address still points to the same slot
+        assert_eq!(run("lea rax sp[3]\nmov sp[0] rax\nmov rbx sp[0]\nmov [rbx] 9\nret sp[3]").unwrap(), Type::Integer(9));
+    }
+
+    #[test]
+    fn commas_separate_operands() {
+        for (with_commas, with_spaces) in [("mov rax, 5", "mov rax 5"), ("add rax, rbx, 1", "add rax rbx 1"), ("call rax, rbx f", "call rax rbx f"), ("ret rax, 1", "ret rax 1")] {
+            assert_eq!(Command::from_str(with_commas).unwrap(), Command::from_str(with_spaces).unwrap(), "{with_commas}");
+        }
+
+        assert_eq!(Command::from_str("call rax,rbx f").unwrap().to_string(), "call rax,rbx f");
+        assert_eq!(Command::from_str("mov rax, \"a, b\"").unwrap(), Command::Mov(Address::Register(Register::Rax), Assignment::Value(Type::String("a, b".to_string()))));
+        assert_eq!(Command::from_str("mov rax, ','").unwrap(), Command::Mov(Address::Register(Register::Rax), Assignment::Value(Type::Char(','))));
+    }
+
+    #[test]
+    fn sleep_advances_the_simulated_clock() {
+        let started = std::time::Instant::now();
+        assert_eq!(run("syscall time\nmov rcx rax\nsyscall sleep 100\nmov rax 50\nsyscall sleep\nsyscall time\nsub rax rax rcx\nret rax").unwrap(), Type::Integer(150));
+        assert!(started.elapsed() < std::time::Duration::from_millis(100));
+
+        match run("syscall sleep -1\nret 0") {
+            Err(ProgramError::Runtime { error, .. }) => assert!(matches!(*error, ProgramError::Memory(MemoryError::OperationError(OperationError::WrongType { .. })))),
+            other => panic!("expected a wrong type, got {other:?}")
+        }
+    }
+
+    #[test]
+    fn sleep_fails_instead_of_overflowing_the_clock() {
+        let mut interpreter = Interpreter::from_str(&format!("syscall sleep {}\nsyscall sleep 1\nret 0", isize::MAX)).unwrap();
+        match interpreter.run() {
+            Err(ProgramError::Runtime { instruction: 1, error, .. }) => assert!(matches!(*error, ProgramError::Memory(MemoryError::ClockOverflow { clock: isize::MAX, milliseconds: 1 }))),
+            other => panic!("expected a clock overflow, got {other:?}")
+        }
+        assert_eq!(interpreter.memory.clock, isize::MAX);
+    }
+
+    #[test]
+    fn getbyte_reads_utf8_bytes() {
+        assert_eq!(run("getbyte rax \"ABC\" 1\nret rax").unwrap(), Type::Integer('B' as isize));
+
+        // the second character starts at the third byte
+        let mut interpreter = Interpreter::from_str("mov rax \"é!\"\ngetbyte rbx rax 0\ngetbyte rcx rax 1\ngetbyte rdx rax 2\nret 0").unwrap();
+        interpreter.run().unwrap();
+        assert_eq!([Register::Rbx, Register::Rcx, Register::Rdx].map(|register| interpreter.memory.register(register)), [Type::Integer(0xC3), Type::Integer(0xA9), Type::Integer('!' as isize)]);
+
+        match run("getbyte rax \"é!\" 3\nret rax") {
+            Err(ProgramError::Runtime { error, .. }) => assert!(matches!(*error, ProgramError::Memory(MemoryError::IndexOutOfBounds { index: 3, length: 3 }))),
+            other => panic!("expected an out of bounds index, got {other:?}")
+        }
+    }
+
+    #[test]
+    fn addresses_round_trip_through_integers() {
+        assert_eq!(run("lea rax sp[3]\naddr2int rbx rax\nadd rbx rbx 1\nint2addr rcx rbx\nmov [rcx] 9\nret sp[4]").unwrap(), Type::Integer(9));
+        assert_eq!(run("lea rax sp[3]\naddr2int rbx rax\nint2addr rcx rbx\nret rcx").unwrap(), Type::Address(Address::StackPointer(3)));
+
+        for index in [64, -1] {
+            match run(&format!("int2addr rax {index}\nret rax")) {
+                Err(ProgramError::Runtime { error, .. }) => assert!(matches!(*error, ProgramError::Memory(MemoryError::IndexOutOfBounds { index: i, length: 64 }) if i == index)),
+                other => panic!("expected an out of bounds index, got {other:?}")
+            }
+        }
+    }
+
+    #[test]
+    fn string_destinations_are_rejected_clearly() {
+        for source in ["mov \"x\" 5", "add \"x\" 1 2", "sete \"x\"", "call \"x\" f"] {
+            let error = Command::from_str(source).unwrap_err().to_string();
+            assert!(error.contains("A string literal cannot be a destination: \"x\""), "{source}: {error}");
+        }
+    }
+
+    #[test]
+    fn dividing_by_zero_fails_cleanly() {
+        assert_eq!(run("div rax, 10, 3\nret rax").unwrap(), Type::Integer(3));
+        assert_eq!(run("div rax, -7, 2\nret rax").unwrap(), Type::Integer(-3));
+
+        match run("div rax, 10, 0\nret rax") {
+            Err(error @ ProgramError::Runtime { .. }) => assert!(error.to_string().contains("Cannot operate on (10, 0): Attempted dividing by zero"), "{error}"),
+            other => panic!("expected a division by zero, got {other:?}")
+        }
+    }
+
+    #[test]
+    fn mod_follows_rust_remainder_semantics() {
+        for (dividend, divisor, remainder) in [(7, 3, 1), (-7, 3, -1), (7, -3, 1), (-7, -3, -1), (isize::MIN, -1, 0)] {
+            assert_eq!(run(&format!("mod rax, {dividend}, {divisor}\nret rax")).unwrap(), Type::Integer(remainder), "{dividend} % {divisor}");
+        }
+
+        match run("mod rax, 5, 0\nret rax") {
+            Err(ProgramError::Runtime { error, .. }) => assert!(matches!(*error, ProgramError::Memory(MemoryError::OperandError { error: OperationError::DivideByZero, .. }))),
+            other => panic!("expected a division by zero, got {other:?}")
+        }
+    }
+
+    #[test]
+    fn bitwise_instructions_work_on_integers_only() {
+        assert_eq!(run("and rax, 6, 3\nret rax").unwrap(), Type::Integer(2));
+        assert_eq!(run("or rax, 6, 3\nret rax").unwrap(), Type::Integer(7));
+        assert_eq!(run("xor rax, 6, 3\nret rax").unwrap(), Type::Integer(5));
+
+        for source in ["and rax, 6, 3", "or rax, 6, 3", "xor rax, 6, 3"] {
+            assert_eq!(Command::from_str(source).unwrap().to_string(), source.replace(',', ""));
+        }
+
+        // a string, an address and an untyped register
+        for setup in ["mov rbx \"s\"", "lea rbx sp[2]", "nop"] {
+            match run(&format!("{setup}\nand rax rbx 1\nret rax")) {
+                Err(ProgramError::Runtime { error, .. }) => assert!(matches!(*error, ProgramError::Memory(MemoryError::OperandError { error: OperationError::WrongType { .. }, .. })), "{setup}"),
+                other => panic!("expected a wrong type for {setup}, got {other:?}")
+            }
+        }
+    }
+
+    #[test]
+    fn neg_negates_integers() {
+        assert_eq!(run("neg rax, 5\nret rax").unwrap(), Type::Integer(-5));
+        assert_eq!(run("mov rbx -3\nneg rax rbx\nret rax").unwrap(), Type::Integer(3));
+        assert_eq!(run(&format!("neg rax {}\nret rax", isize::MIN)).unwrap(), Type::Integer(isize::MIN));
+
+        match run("neg rax, \"hi\"\nret rax") {
+            Err(ProgramError::Runtime { error, .. }) => assert!(matches!(*error, ProgramError::Memory(MemoryError::OperandError { error: OperationError::WrongType { .. }, .. }))),
+            other => panic!("expected a wrong type, got {other:?}")
+        }
+    }
+
+    #[test]
+    fn push_and_pop_grow_down_from_the_end_of_the_stack() {
+        assert_eq!(run("push 7\nmov sp[0] 1\npop rax\nret rax").unwrap(), Type::Integer(7));
+        assert_eq!(run("push 1\npush 2\npop rax\npop rbx\nsub rax rax rbx\nret rax").unwrap(), Type::Integer(1));
+
+        let mut interpreter = Interpreter::from_str("push 7\nret 0").unwrap();
+        interpreter.run().unwrap();
+        assert_eq!((interpreter.memory.stack_pointer, &interpreter.memory.stack[63]), (63, &Type::Integer(7)));
+
+        match run("pop rax\nret rax") {
+            Err(ProgramError::Runtime { error, .. }) => assert!(matches!(*error, ProgramError::Memory(MemoryError::SegmentationFault(message)) if message == "Cannot pop into rax, the stack is empty")),
+            other => panic!("expected an empty stack, got {other:?}")
+        }
+
+        let overflow = "push 0\n".repeat(65) + "ret 0";
+        match run(&overflow) {
+            Err(ProgramError::Runtime { instruction: 64, error, .. }) => assert!(matches!(*error, ProgramError::Memory(MemoryError::SegmentationFault(message)) if message == "Cannot push 0, the stack is full")),
+            other => panic!("expected a full stack, got {other:?}")
+        }
+    }
+
+    #[test]
+    fn cmp_parses_with_and_without_destination() {
+        assert_eq!(Command::from_str("cmp rax, 3, 5").unwrap(), Command::Compare(Some(Address::Register(Register::Rax)), Assignment::Value(Type::Integer(3)), Assignment::Value(Type::Integer(5))));
+        assert_eq!(Command::from_str("cmp rbx 5").unwrap(), Command::Compare(None, Assignment::Address(Address::Register(Register::Rbx)), Assignment::Value(Type::Integer(5))));
+        assert_eq!(Command::from_str("cmp rax, 3, 5").unwrap().to_string(), "cmp rax 3 5");
+        assert_eq!(Command::from_str("cmp rbx 5").unwrap().to_string(), "cmp rbx 5");
+        assert!(Command::from_str("cmp \"x\" 3 5").is_err());
+    }
+
+    #[test]
+    fn cmp_stores_the_sign_of_the_difference() {
+        assert_eq!(run("cmp rax, 3, 5\nret rax").unwrap(), Type::Integer(-1));
+        assert_eq!(run("cmp rax, 5, 5\nret rax").unwrap(), Type::Integer(0));
+        assert_eq!(run("cmp rax, 7, 5\nret rax").unwrap(), Type::Integer(1));
+        assert_eq!(run("cmp rax, 'a', 'b'\nret rax").unwrap(), Type::Integer(-1));
+
+        let mut interpreter = Interpreter::from_str("mov rax 9\ncmp rax 5\nret rax").unwrap();
+        assert_eq!(interpreter.run().unwrap(), Type::Integer(9));
+        assert_eq!(interpreter.memory.flags.sign_value(), Some(1));
+
+        match run("cmp rax, \"a\", 5\nret rax") {
+            Err(ProgramError::Runtime { error, .. }) => assert!(matches!(*error, ProgramError::Memory(MemoryError::OperationError(OperationError::TryCmp(_))))),
+            other => panic!("expected incompatible types, got {other:?}")
+        }
+    }
+
+    #[test]
+    fn conditional_jumps_parse_with_and_without_assignment() {
+        let rax = Some(Assignment::Address(Address::Register(Register::Rax)));
+        let label = || JumpDestination::Label("label".to_string());
+
+        assert_eq!(Command::from_str("je rax, label").unwrap(), Command::JumpEqual(rax.clone(), label()));
+        assert_eq!(Command::from_str("jne rax label").unwrap(), Command::JumpNotEqual(rax.clone(), label()));
+        assert_eq!(Command::from_str("jl rax label").unwrap(), Command::JumpLess(rax.clone(), label()));
+        assert_eq!(Command::from_str("jg rax label").unwrap(), Command::JumpGreater(rax, label()));
+        assert_eq!(Command::from_str("je label").unwrap(), Command::JumpEqual(None, label()));
+
+        for source in ["je rax label", "jne rax label", "jl rax label", "jg rax label", "je label"] {
+            assert_eq!(Command::from_str(source).unwrap().to_string(), source);
+        }
+    }
+
+    #[test]
+    fn conditional_jumps_branch_on_cmp_results() {
+        for (setup, jump, expected) in [
+            ("cmp rcx 3 3", "je rcx", 7), ("cmp rcx 3 5", "je rcx", 1),
+            ("cmp rcx 3 5", "jne rcx", 7), ("cmp rcx 3 3", "jne rcx", 1),
+            ("cmp rcx 3 5", "jl rcx", 7), ("cmp rcx 5 3", "jl rcx", 1),
+            ("cmp rcx 5 3", "jg rcx", 7), ("cmp rcx 3 5", "jg rcx", 1),
+            ("cmp 3 3", "je", 7), ("cmp 3 5", "je", 1),
+        ] {
+            assert_eq!(branches(setup, jump), Type::Integer(expected), "{setup}, {jump}");
+        }
+    }
+
+    #[test]
+    fn jle_and_jge_branch_on_equal_results_too() {
+        for (setup, jump, expected) in [
+            ("cmp rcx 3 5", "jle rcx", 7), ("cmp rcx 3 3", "jle rcx", 7), ("cmp rcx 5 3", "jle rcx", 1),
+            ("cmp rcx 5 3", "jge rcx", 7), ("cmp rcx 3 3", "jge rcx", 7), ("cmp rcx 3 5", "jge rcx", 1),
+            ("cmp 3 3", "jle", 7), ("cmp 3 5", "jge", 1),
+        ] {
+            assert_eq!(branches(setup, jump), Type::Integer(expected), "{setup}, {jump}");
+        }
+
+        // falling through pops the frame the jump pushed
+        for (setup, jump) in [("cmp rcx 5 3", "jle rcx"), ("cmp rcx 3 5", "jge rcx")] {
+            let source = format!("{setup}\n{jump} hit\nsyscall depth\nret rax\nhit:\nleave");
+            assert_eq!(run(&source).unwrap(), Type::Integer(0), "{jump}");
+        }
+    }
+
+    #[test]
+    fn cmp_without_destination_sets_the_flags_for_jumps() {
+        let mut interpreter = Interpreter::from_str("mov rax 0\ncmp rax, 0\nret rax").unwrap();
+        interpreter.run().unwrap();
+        assert!(interpreter.memory.flags.zero() && !interpreter.memory.flags.sign());
+
+        let mut interpreter = Interpreter::from_str("mov rax -2\ncmp rax, 0\nret rax").unwrap();
+        interpreter.run().unwrap();
+        assert!(!interpreter.memory.flags.zero() && interpreter.memory.flags.sign());
+
+        assert_eq!(branches("mov rax 0\ncmp rax, 0", "je"), Type::Integer(7));
+        assert_eq!(branches("mov rax 3\ncmp rax, 0", "je"), Type::Integer(1));
+        assert_eq!(branches("mov rax -2\ncmp rax, 0", "jl"), Type::Integer(7));
+    }
+
+    #[test]
+    fn floats_promote_integers() {
+        assert_eq!(run("mov rax, 3.5\nret rax").unwrap(), Type::Float(3.5));
+        assert_eq!(run("mov rax, 3.5\nadd rax, rax, 1\nret rax").unwrap(), Type::Float(4.5));
+        assert_eq!(run("mul rax, 2, 0.25\nret rax").unwrap(), Type::Float(0.5));
+
+        let (mut interpreter, output) = captured("syscall printf \"{}\" 4.5\nret 0");
+        interpreter.run().unwrap();
+        assert_eq!(text(&output), "4.5\n");
+    }
+
+    #[test]
+    fn jumps_branch_on_boolean_registers() {
+        assert_eq!(branches("mov rcx true", "jne rcx"), Type::Integer(7));
+        assert_eq!(branches("mov rcx false", "jne rcx"), Type::Integer(1));
+        assert_eq!(branches("mov rcx false", "je rcx"), Type::Integer(7));
+        assert_eq!(branches("mov rcx true", "je rcx"), Type::Integer(1));
+
+        let mut interpreter = Interpreter::from_str("cmp 3 3\nsete rax\nstreq rbx \"a\" \"b\"\nret rax").unwrap();
+        interpreter.memory.boolean_comparisons = true;
+        assert_eq!(interpreter.run().unwrap(), Type::Boolean(true));
+        assert_eq!(interpreter.memory.register(Register::Rbx), Type::Boolean(false));
+    }
+
+    #[test]
+    fn boolean_comparisons_keep_the_control_flow() {
+        for boolean_comparisons in [false, true] {
+            let mut interpreter = Interpreter::from_str("cmp 3 3\nsete rax\nje rax equal\nret 1\nequal:\nret 7").unwrap();
+            interpreter.memory.boolean_comparisons = boolean_comparisons;
+            assert_eq!(interpreter.run().unwrap(), Type::Integer(1), "boolean_comparisons: {boolean_comparisons}");
+        }
+    }
+
+    #[test]
+    fn ordered_jumps_reject_booleans() {
+        for jump in ["jl", "jg", "jle", "jge"] {
+            for value in ["true", "false"] {
+                match run(&format!("mov rcx {value}\n{jump} rcx yes\nret 1\nyes:\nret 7")) {
+                    Err(ProgramError::Runtime { instruction, error, .. }) => {
+                        assert_eq!(instruction, 1);
+                        assert!(matches!(*error, ProgramError::Memory(MemoryError::OperationError(OperationError::WrongType { .. }))), "{jump} {value}: {error}");
+                    },
+                    other => panic!("{jump} {value}: expected a runtime error, got {other:?}")
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hexadecimal_immediates_and_indices_run() {
+        assert_eq!(run("mov sp[0x10] 0x1F\nadd rax sp[16] -0x10\nret rax").unwrap(), Type::Integer(15));
+    }
+
+    #[test]
+    fn character_literals_keep_spaces_and_escaped_quotes() {
+        assert_eq!(run("mov rax ' '\nret rax").unwrap(), Type::Char(' '));
+        assert_eq!(run("mov rax '\\''\nret rax").unwrap(), Type::Char('\''));
+        assert_eq!(Command::from_str("mov rax '\\''").unwrap().to_string(), "mov rax '\\''");
+    }
+
+    #[test]
+    fn printf_prints_decoded_escape_sequences() {
+        let (mut interpreter, output) = captured("mov rax \"line\\nbreak\"\nsyscall prints\nsyscall printf \"a\\t{}\\\\\" 1\nret 0");
+        interpreter.run().unwrap();
+        assert_eq!(text(&output), "line\nbreak\na\t1\\\n");
+    }
+
+    #[test]
+    fn read_stores_the_next_line_of_the_input_into_rax() {
+        let mut interpreter = Interpreter::from_str("syscall read\nmov rbx rax\nsyscall read\nmov rcx rax\nsyscall read\nret rax").unwrap()
+            .with_input(Rc::new(RefCell::new(std::io::Cursor::new("hello world\nsecond\n"))));
+
+        assert_eq!(interpreter.run().unwrap(), Type::Untyped);
+        assert_eq!(interpreter.memory.register(Register::Rbx), Type::String("hello world".to_string()));
+        assert_eq!(interpreter.memory.register(Register::Rcx), Type::String("second".to_string()));
+    }
+
+    #[test]
+    fn read_reports_failing_input() {
+        struct Failing;
+
+        impl std::io::Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("closed"))
+            }
+        }
+
+        let mut interpreter = Interpreter::from_str("syscall read\nret rax").unwrap()
+            .with_input(Rc::new(RefCell::new(std::io::BufReader::new(Failing))));
+
+        match interpreter.run() {
+            Err(ProgramError::Runtime { instruction, error, .. }) => {
+                assert_eq!(instruction, 0);
+                assert_eq!(error.to_string(), "Cannot read input: closed");
+            },
+            other => panic!("expected a runtime error, got {other:?}")
+        }
+    }
+
+    #[test]
+    fn repeating_a_string_beyond_the_limit_fails() {
+        match run("mov rax \"ab\"\nmul rax rax 100000000000000\nret rax") {
+            Err(ProgramError::Runtime { instruction, error, .. }) => {
+                assert_eq!(instruction, 1);
+                assert!(matches!(*error, ProgramError::Memory(MemoryError::OperandError { error: OperationError::StringTooLong { length: 200000000000000, .. }, .. })));
+            },
+            other => panic!("expected a runtime error, got {other:?}")
+        }
+    }
+}
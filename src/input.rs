@@ -0,0 +1,56 @@
+use std::cell::RefCell;
+use std::fmt::{Debug, Formatter};
+use std::io::{BufRead, BufReader};
+use std::rc::Rc;
+
+/// The reader `syscall read` reads lines from. Clones share the same reader.
+#[derive(Clone)]
+pub struct Input {
+    reader: Rc<RefCell<dyn BufRead>>,
+}
+
+impl Input {
+    pub fn new(reader: Rc<RefCell<dyn BufRead>>) -> Self {
+        Self { reader }
+    }
+
+    /// Reads the next line without its line ending. Returns `None`, if the end of the input is reached
+    pub fn read_line(&self) -> std::io::Result<Option<String>> {
+        let mut line = String::new();
+        if self.reader.borrow_mut().read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        let length = line.trim_end_matches(['\n', '\r']).len();
+        line.truncate(length);
+        Ok(Some(line))
+    }
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Self::new(Rc::new(RefCell::new(BufReader::new(std::io::stdin()))))
+    }
+}
+
+impl Debug for Input {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Input")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use super::*;
+
+    #[test]
+    fn lines_are_read_without_their_line_endings() {
+        let input = Input::new(Rc::new(RefCell::new(Cursor::new("first line\r\n\nlast"))));
+
+        assert_eq!(input.read_line().unwrap(), Some("first line".to_string()));
+        assert_eq!(input.read_line().unwrap(), Some(String::new()));
+        assert_eq!(input.clone().read_line().unwrap(), Some("last".to_string()));
+        assert_eq!(input.read_line().unwrap(), None);
+    }
+}
@@ -1,60 +1,172 @@
-use std::fmt::{Display, Formatter};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Debug, Display, Formatter};
+use std::io::{BufRead, Write};
+use std::rc::Rc;
 use std::str::FromStr;
 
 use thiserror::Error;
-use crate::assignment::Type;
+use crate::assignment::{Assignment, OperationError, Radix, Type};
 
 use crate::command::Command;
 use crate::address::Address;
 use crate::jump::JumpDestination;
-use crate::memory::Memory;
+use crate::memory::{Flags, Memory, MemoryError};
+use crate::input::Input;
+use crate::output::Output;
 use crate::program_error::{ParseError, ProgramError};
+use crate::register::Register;
 
 #[derive(Debug)]
 pub struct Interpreter {
     pub program_pointer: usize,
     pub memory: Memory,
     pub source_code: Vec<Command>,
+    /// The original text the program was parsed from, if available
+    pub program_text: Option<String>,
+    /// Line number in the program text for each command in `source_code`
+    pub source_lines: Vec<usize>,
+    /// Prints runs of equal values on the stack as a single range
+    pub compact_stack_print: bool,
+    /// Base in which integers in registers and on the stack are printed
+    pub display_radix: Radix,
+    /// Programs by name, which can be run with `syscall spawn`
+    pub programs: HashMap<String, String>,
+    /// How many spawned programs this interpreter is nested in
+    pub spawn_depth: usize,
+    /// Variables `syscall getenv` reads. If none, the environment of the process is used
+    pub env: Option<HashMap<String, String>>,
+    /// The stack was initialized with zeros instead of untyped values
+    pub zeroed_stack: bool,
+    /// Halting while stack frames are still open is an error, e.g. after a `jmp` which never returned
+    pub strict_frames: bool,
+    /// If some, every instruction uses gas and the program fails with `OutOfGas`, once the limit would be exceeded
+    pub gas_limit: Option<u64>,
+    /// Gas used by every instruction executed so far, while a gas limit is set
+    pub gas_used: u64,
+    /// Gas costs by mnemonic, e.g. `mov`. Instructions which aren't in the map cost `DEFAULT_GAS_COST`
+    pub gas_costs: HashMap<String, u64>,
+    /// Registers and flags saved with `savectx` by their name
+    pub contexts: HashMap<String, (RegisterMemory, Flags)>,
+    step_callback: Option<StepCallback>,
+    execution_counts: Vec<usize>,
 }
 
-fn pretty_print_stack(min: usize, stack: &[Type]) -> Vec<String> {
-    let mut printing_stack = vec![];
+pub type StepFn = dyn FnMut(&Command, &Memory);
 
-    pretty_print_stack_helper(min, stack, &mut printing_stack);
-    printing_stack
+/// Closure invoked after every executed instruction
+pub struct StepCallback(Box<StepFn>);
+
+impl Debug for StepCallback {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "StepCallback")
+    }
 }
 
-fn pretty_print_stack_helper(min: usize, stack: &[Type], printing_stack: &mut Vec<String>) {
-    if let Some(typed_position) = stack.iter().enumerate().position(|(index, a)| index >= min && *a != Type::Untyped) {
-        if typed_position != min {
-            printing_stack.push(format!("{min}..{}: {}", typed_position - 1,Type::Untyped));
+/// Gas, which an instruction without configured cost uses
+pub const DEFAULT_GAS_COST: u64 = 1;
+
+/// Amount of slots on the stack
+pub const STACK_SIZE: usize = 64;
+
+/// Maximum amount of nested spawned programs, so programs spawning each other can't recurse forever
+pub const MAX_SPAWN_DEPTH: usize = 16;
+
+/// Coalesces blank slots, which weren't written yet, into ranges. If compact, runs of equal values are coalesced as well.
+/// Ranges are inclusive and start at the real index of their first slot
+fn pretty_print_stack(min: usize, stack: &[Type], blank: &Type, compact: bool, radix: Radix) -> Vec<String> {
+    let mut printing_stack = vec![];
+    let mut start = min;
+
+    while let Some(value) = stack.get(start) {
+        let mut end = start;
+        if compact || value == blank {
+            while stack.get(end + 1) == Some(value) {
+                end += 1;
+            }
         }
 
-        printing_stack.push(format!("{typed_position}: {}", stack[typed_position]));
-        pretty_print_stack_helper(typed_position + 1, stack, printing_stack);
-    } else {
-        printing_stack.push(format!("{min}..{end}: {}", Type::Untyped, end = stack.len()));
+        if end == start {
+            printing_stack.push(format!("{start}: {}", value.with_radix(radix)));
+        } else {
+            printing_stack.push(format!("{start}..{end}: {}", value.with_radix(radix)));
+        }
+
+        start = end + 1;
     }
+
+    printing_stack
 }
 
 impl Display for Interpreter {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("Interpreter")
-            .field("rax", &self.memory.rax)
-            .field("rbx", &self.memory.rbx)
-            .field("rcx", &self.memory.rcx)
-            .field("stack", &pretty_print_stack(0, &self.memory.stack))
-            .finish()
+        let mut debug_struct = f.debug_struct("Interpreter");
+
+        // rax, rbx and rcx are always printed, other registers only once they are used
+        for register in Register::ALL {
+            let value = self.memory.register(register);
+            if matches!(register, Register::Rax | Register::Rbx | Register::Rcx) || value != Type::Untyped {
+                debug_struct.field(&register.to_string(), &value.with_radix(self.display_radix));
+            }
+        }
+
+        debug_struct
+            .field("flags", &self.memory.flags)
+            .field("stack", &pretty_print_stack(0, &self.memory.stack, &self.blank_stack_value(), self.compact_stack_print, self.display_radix));
+
+        if !self.memory.heap.is_empty() {
+            debug_struct.field("heap", &pretty_print_stack(0, &self.memory.heap, &Type::Untyped, self.compact_stack_print, self.display_radix));
+        }
+
+        debug_struct.finish()
     }
 }
 
-pub type RegisterMemory = (Type, Type, Type);
+/// Result of executing a single instruction
+#[derive(Debug, Clone, PartialEq)]
+pub enum StepOutcome {
+    /// The instruction at the index was executed and the program continues
+    Executed(usize),
+    /// The program halted with the value
+    Halted(Type),
+    /// The instruction at the index was `syscall yield`, so the program gives up control until it's resumed
+    Yielded(usize),
+}
+
+/// A slot, which holds different values in two interpreter states
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateDelta {
+    pub address: Address,
+    pub before: Type,
+    pub after: Type,
+}
+
+impl Display for StateDelta {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {} -> {}", self.address.disassemble(), self.before, self.after)
+    }
+}
 
+/// An interpreter taken out of execution, e.g. by a scheduler switching between programs.
+/// Resuming it continues after the instruction it was suspended at
 #[derive(Debug)]
+pub struct SuspendedInterpreter {
+    interpreter: Interpreter,
+}
+
+impl SuspendedInterpreter {
+    pub fn resume(self) -> Interpreter {
+        self.interpreter
+    }
+}
+
+pub type RegisterMemory = HashMap<Register, Type>;
+
+#[derive(Debug, Clone)]
 pub struct StackFrame {
     pub return_address: usize,
     pub entered_with_jmp: bool,
-    pub destination: Option<Address>,
+    pub destinations: Vec<Address>,
     pub register_state: RegisterMemory,
 }
 
@@ -64,32 +176,56 @@ impl FromStr for Interpreter {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut commands = vec![];
+        let mut source_lines = vec![];
 
-        for line in s.lines() {
-            if line.is_empty() { continue; }
+        for (line_number, line) in s.lines().enumerate() {
+            if line.trim().is_empty() { continue; }
             if line.trim().starts_with(';') { continue; }
 
             commands.push(Command::from_str(line)?);
+            source_lines.push(line_number + 1);
         }
 
-        Ok(Self {
-            memory: Memory {
-                rax: Type::Untyped,
-                rbx: Type::Untyped,
-                rcx: Type::Untyped,
-                stack_frame: Vec::new(),
-                stack: vec![Type::Untyped; 64],
-            },
-            program_pointer: 0,
-            source_code: commands,
-        })
+        let strings = string_symbols(&commands);
+        for (command, line_number) in commands.iter().zip(&source_lines) {
+            if let Some(error) = undefined_symbol(command, &strings, *line_number) {
+                return Err(error);
+            }
+        }
+
+        Ok(Self::new(commands, source_lines, s))
     }
 }
 
+/// Names of the strings defined with `.string`
+fn string_symbols(commands: &[Command]) -> HashSet<String> {
+    commands.iter()
+        .filter_map(|command| match command {
+            Command::StringData(name, _) => Some(name.clone()),
+            _ => None
+        })
+        .collect()
+}
+
+/// Fails for the first symbol of the command, which isn't defined with `.string`. Strings may be defined after
+/// the line using them, so typos like `mov rax rxa` are caught before running the program
+fn undefined_symbol(command: &Command, strings: &HashSet<String>, line_number: usize) -> Option<ParseError> {
+    command.addresses().into_iter().find_map(|address| match address {
+        Address::Symbol(name) if !strings.contains(name) => {
+            Some(ParseError::new(&format!("Unknown symbol: {name} in line {line_number}, define it with .string {name} \"...\"")))
+        },
+        _ => None
+    })
+}
+
 #[derive(Debug, Error)]
 pub enum SemanticError {
     ReturnMissing { label: String },
     LeaveMissing { label: String },
+    DiscardedReturn { label: String },
+    ReturnTypeMismatch { label: String, expected: String, actual: Type },
+    /// The label returns the result of a call to a label, which is declared to return another type
+    CallTypeMismatch { label: String, expected: String, callee: String, actual: String },
 }
 
 impl Display for SemanticError {
@@ -97,13 +233,181 @@ impl Display for SemanticError {
         write!(f, "{}", match self {
             SemanticError::ReturnMissing { label } => format!("The label '{label}' is used with an expected return value, but no `ret ASSIGNMENT` is provided for all code paths"),
             SemanticError::LeaveMissing { label } => format!("The label '{label}' is used with a leave command, but no leave command is provided in all code paths"),
+            SemanticError::DiscardedReturn { label } => format!("The label '{label}' is called without expecting a return value, but returns one with `ret ASSIGNMENT`, which is discarded"),
+            SemanticError::ReturnTypeMismatch { label, expected, actual } => format!("The label '{label}' is declared to return {expected}, but returns {actual}"),
+            SemanticError::CallTypeMismatch { label, expected, callee, actual } => format!("The label '{label}' is declared to return {expected}, but returns the {actual} returned by '{callee}'"),
         })
     }
 }
 
 impl Interpreter {
+    fn new(source_code: Vec<Command>, source_lines: Vec<usize>, program_text: &str) -> Self {
+        let execution_counts = vec![0; source_code.len()];
+        let strings = source_code.iter()
+            .filter_map(|command| match command {
+                Command::StringData(name, value) => Some((name.clone(), value.clone())),
+                _ => None
+            })
+            .collect();
+
+        Self {
+            memory: Memory {
+                registers: HashMap::new(),
+                flags: Flags::default(),
+                flag_arithmetic: false,
+                strict_printf: false,
+                boolean_comparisons: false,
+                stack_frame: Vec::new(),
+                output: Output::default(),
+                input: Input::default(),
+                stack: Rc::new(vec![Type::Untyped; STACK_SIZE]),
+                stack_pointer: STACK_SIZE,
+                heap: Vec::new(),
+                heap_allocations: Vec::new(),
+                strings,
+                clock: 0,
+            },
+            program_pointer: 0,
+            source_code,
+            program_text: Some(program_text.to_string()),
+            source_lines,
+            compact_stack_print: false,
+            display_radix: Radix::default(),
+            programs: HashMap::new(),
+            spawn_depth: 0,
+            env: None,
+            zeroed_stack: false,
+            strict_frames: false,
+            gas_limit: None,
+            gas_used: 0,
+            gas_costs: HashMap::new(),
+            contexts: HashMap::new(),
+            step_callback: None,
+            execution_counts,
+        }
+    }
+
+    /// Parses the program without aborting on the first invalid line. Invalid lines are replaced
+    /// with `nop` and their errors are returned together with their line number
+    pub fn from_str_lenient(s: &str) -> (Interpreter, Vec<(usize, ParseError)>) {
+        let mut commands = vec![];
+        let mut source_lines = vec![];
+        let mut errors = vec![];
+
+        for (line_number, line) in s.lines().enumerate() {
+            if line.trim().is_empty() { continue; }
+            if line.trim().starts_with(';') { continue; }
+
+            match Command::from_str(line) {
+                Ok(command) => commands.push(command),
+                Err(error) => {
+                    errors.push((line_number + 1, error));
+                    commands.push(Command::Nop);
+                }
+            }
+            source_lines.push(line_number + 1);
+        }
+
+        let strings = string_symbols(&commands);
+        for (command, line_number) in commands.iter_mut().zip(&source_lines) {
+            if let Some(error) = undefined_symbol(command, &strings, *line_number) {
+                errors.push((*line_number, error));
+                *command = Command::Nop;
+            }
+        }
+        errors.sort_by_key(|(line_number, _)| *line_number);
+
+        (Self::new(commands, source_lines, s), errors)
+    }
+
+    /// Makes the program available to `syscall spawn` under the name
+    pub fn register_program(&mut self, name: &str, program: &str) -> Result<(), ParseError> {
+        Interpreter::from_str(program)?;
+        self.programs.insert(name.to_string(), program.to_string());
+
+        Ok(())
+    }
+
+    /// Runs the program registered under the name to completion, sharing the output and
+    /// the registered programs with this interpreter
+    fn spawn(&self, name: &str) -> Result<Type, ProgramError> {
+        if self.spawn_depth >= MAX_SPAWN_DEPTH {
+            return Err(ProgramError::SpawnDepthExceeded(MAX_SPAWN_DEPTH));
+        }
+
+        let program = self.programs.get(name).ok_or_else(|| ProgramError::ProgramNotFound(name.to_string()))?;
+
+        let mut child = Interpreter::from_str(program)?;
+        child.memory.output = self.memory.output.clone();
+        child.memory.input = self.memory.input.clone();
+        child.programs = self.programs.clone();
+        child.spawn_depth = self.spawn_depth + 1;
+        child.env = self.env.clone();
+
+        child.run()
+    }
+
+    /// Redirects everything the program prints into the writer
+    pub fn with_output(mut self, writer: Rc<RefCell<dyn Write>>) -> Self {
+        self.memory.output = Output::new(writer);
+        self
+    }
+
+    /// Reads the lines for `syscall read` from the reader instead of stdin
+    pub fn with_input(mut self, reader: Rc<RefCell<dyn BufRead>>) -> Self {
+        self.memory.input = Input::new(reader);
+        self
+    }
+
+    /// Reads environment variables from the map instead of the environment of the process
+    pub fn with_env(mut self, env: HashMap<String, String>) -> Self {
+        self.env = Some(env);
+        self
+    }
+
+    /// Initializes every slot of the stack with 0 instead of an untyped value, like zero-initialized statics in C
+    pub fn with_zeroed_stack(mut self) -> Self {
+        self.memory.stack = Rc::new(vec![Type::Integer(0); self.memory.stack.len()]);
+        self.zeroed_stack = true;
+        self
+    }
+
+    /// Fails with `LeakedStackFrames`, if the program halts while stack frames are still open
+    pub fn with_strict_frames(mut self) -> Self {
+        self.strict_frames = true;
+        self
+    }
+
+    /// Meters the execution with gas. Every instruction costs `DEFAULT_GAS_COST`, unless configured with `with_gas_cost`
+    pub fn with_gas_limit(mut self, limit: u64) -> Self {
+        self.gas_limit = Some(limit);
+        self
+    }
+
+    /// Sets the gas, which every instruction with the mnemonic uses
+    pub fn with_gas_cost(mut self, mnemonic: &str, cost: u64) -> Self {
+        self.gas_costs.insert(mnemonic.to_string(), cost);
+        self
+    }
+
+    /// Value of stack slots, which weren't written yet
+    fn blank_stack_value(&self) -> Type {
+        if self.zeroed_stack { Type::Integer(0) } else { Type::Untyped }
+    }
+
+    /// Registers a closure, which is invoked with the command and the memory after every executed instruction.
+    /// Replaces the previously registered closure
+    pub fn on_step(&mut self, f: impl FnMut(&Command, &Memory) + 'static) {
+        self.step_callback = Some(StepCallback(Box::new(f)));
+    }
+
+    /// Returns how many times the instruction at each index was executed
+    pub fn execution_counts(&self) -> &[usize] {
+        &self.execution_counts
+    }
+
     pub fn semantic_check(&self) -> Result<(), ProgramError> {
-        for command in &self.source_code {
+        for (index, command) in self.source_code.iter().enumerate() {
             // if call is ran with a label, this label must have a ret command in all code paths
             match command {
                 Command::CallRet(_, jump_destination) => {
@@ -115,6 +419,12 @@ impl Interpreter {
                 Command::Jmp(jump_destination) => {
                     jump_destination.ends_with(self, |command| matches!(command, Command::Return(_) | Command::Leave), |target_label| SemanticError::LeaveMissing { label: target_label.to_string() })?;
                 },
+                Command::JumpTable(_, jump_destinations) => {
+                    for jump_destination in jump_destinations {
+                        jump_destination.ends_with(self, |command| matches!(command, Command::Return(_) | Command::Leave), |target_label| SemanticError::LeaveMissing { label: target_label.to_string() })?;
+                    }
+                },
+                Command::Label(label, Some(return_type)) => self.check_return_type(index, label, return_type)?,
                 _ => {}
             }
         }
@@ -122,75 +432,525 @@ impl Interpreter {
         Ok(())
     }
 
-    fn search_label_jump(&mut self, target_label: &str) -> Result<(), ProgramError> {
-        let potential_index = self.source_code.iter().position(|a|
-            matches!(a, Command::Label(source_label) if *source_label == *target_label)
+    /// Checks, that the label at the index returns values of its declared type. Besides literals, registers are checked,
+    /// if a literal or the result of a call to a label with a declared return type was moved into them earlier in the label.
+    /// Values computed at runtime, e.g. by `add`, or read from the stack or the heap aren't checked, neither are
+    /// the commands using the result of a call
+    fn check_return_type(&self, index: usize, label: &str, return_type: &str) -> Result<(), SemanticError> {
+        // the command, which last wrote a register with a known type
+        let mut origins: HashMap<Register, &Command> = HashMap::new();
+
+        for command in self.source_code.iter().skip(index + 1).take_while(|command| !matches!(command, Command::Label(_, _))) {
+            match command {
+                Command::Return(assignments) => for assignment in assignments {
+                    let value = match assignment {
+                        Assignment::Value(value) => value,
+                        Assignment::Address(Address::Register(register)) => match origins.get(register) {
+                            Some(Command::Mov(_, Assignment::Value(value))) => value,
+                            Some(Command::CallRet(_, callee)) => match self.declared_return_type(callee) {
+                                Some(actual) if actual != return_type => {
+                                    return Err(SemanticError::CallTypeMismatch { label: label.to_string(), expected: return_type.to_string(), callee: callee.to_string(), actual: actual.to_string() });
+                                },
+                                _ => continue
+                            },
+                            _ => continue
+                        },
+                        Assignment::Address(_) => continue
+                    };
+
+                    if value.type_name() != return_type {
+                        return Err(SemanticError::ReturnTypeMismatch { label: label.to_string(), expected: return_type.to_string(), actual: value.clone() });
+                    }
+                },
+                Command::Mov(Address::Register(destination), Assignment::Value(_)) => {
+                    origins.insert(*destination, command);
+                },
+                Command::Mov(Address::Register(destination), Assignment::Address(Address::Register(source))) => {
+                    match origins.get(source).copied() {
+                        Some(origin) => origins.insert(*destination, origin),
+                        None => origins.remove(destination)
+                    };
+                },
+                Command::CallRet(destinations, _) => {
+                    // the callee may overwrite every register
+                    origins.clear();
+                    for destination in destinations {
+                        if let Address::Register(register) = destination {
+                            origins.insert(*register, command);
+                        }
+                    }
+                },
+                Command::CallVoid(_) | Command::Syscall(_, _) | Command::RotateRegisters | Command::LoadContext(_) | Command::Unwind(_) => origins.clear(),
+                // a reference may point to a register
+                command if command.addresses().iter().any(|address| matches!(address, Address::Reference(_))) => origins.clear(),
+                command => for address in command.addresses() {
+                    if let Address::Register(register) = address {
+                        origins.remove(register);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the return type the label of the jump destination declares
+    fn declared_return_type(&self, jump_destination: &JumpDestination) -> Option<&str> {
+        let index = jump_destination.resolve(self).ok()?;
+
+        match self.source_code.get(index) {
+            Some(Command::Label(_, Some(return_type))) => Some(return_type),
+            _ => None
+        }
+    }
+
+    /// Collects issues, which don't prevent the program from running, but are most likely mistakes
+    pub fn semantic_warnings(&self) -> Result<Vec<SemanticError>, ProgramError> {
+        let mut warnings = vec![];
+
+        for command in &self.source_code {
+            if let Command::CallVoid(jump_destination) = command {
+                if jump_destination.contains(self, |command| matches!(command, Command::Return(_)))? {
+                    warnings.push(SemanticError::DiscardedReturn { label: jump_destination.to_string() });
+                }
+            }
+        }
+
+        Ok(warnings)
+    }
+
+    /// Returns the (from, to) command indices of every jump, call and conditional jump, whose target label can be resolved.
+    /// Computed jumps like `mov pc rax` aren't known statically and are omitted
+    pub fn edges(&self) -> Vec<(usize, usize)> {
+        self.source_code.iter().enumerate().flat_map(|(from, command)| {
+            let jump_destinations = match command {
+                Command::CallRet(_, jump_destination) | Command::CallVoid(jump_destination) | Command::Jmp(jump_destination) |
+                Command::JumpLess(_, jump_destination) | Command::JumpGreater(_, jump_destination) |
+                Command::JumpLessEqual(_, jump_destination) | Command::JumpGreaterEqual(_, jump_destination) |
+                Command::JumpNotEqual(_, jump_destination) | Command::JumpEqual(_, jump_destination) => vec![jump_destination],
+                Command::JumpTable(_, jump_destinations) => jump_destinations.iter().collect(),
+                _ => vec![]
+            };
+
+            jump_destinations.into_iter()
+                .filter_map(|jump_destination| jump_destination.resolve(self).ok())
+                .map(move |to| (from, to))
+                .collect::<Vec<_>>()
+        }).collect()
+    }
+
+    /// Checks the semantics of the program and runs it until it returns
+    pub fn run(&mut self) -> Result<Type, ProgramError> {
+        self.semantic_check()?;
+        self.run_unchecked()
+    }
+
+    /// Runs the program until it returns, without checking its semantics first. Programs violating
+    /// the semantic check, e.g. ones looping forever by design, can be run this way. But a missing
+    /// `ret` or `leave` is then only noticed, when the program falls off its end or off a label.
+    ///
+    /// Falling off the end of the program exits with 0. The output is flushed afterward,
+    /// even if the program failed
+    pub fn run_unchecked(&mut self) -> Result<Type, ProgramError> {
+        let result = self.run_until_halt(None);
+        self.flush()?;
+
+        result
+    }
+
+    /// Like `run`, but fails with `StepLimitExceeded`, if the program didn't halt within the amount of steps
+    pub fn run_bounded(&mut self, max_steps: usize) -> Result<Type, ProgramError> {
+        self.semantic_check()?;
+
+        let result = self.run_until_halt(Some(max_steps));
+        self.flush()?;
+
+        result
+    }
+
+    /// Writes everything the program printed so far to the output
+    pub fn flush(&self) -> Result<(), ProgramError> {
+        self.memory.output.flush().map_err(MemoryError::from)?;
+        Ok(())
+    }
+
+    /// Runs the program until it yields with `syscall yield` or halts. The output is flushed afterward,
+    /// so programs sharing a writer print in the order they ran
+    pub fn run_until_yield(&mut self) -> Result<StepOutcome, ProgramError> {
+        let result = loop {
+            match self.step() {
+                Ok(StepOutcome::Executed(_)) => {},
+                outcome => break outcome
+            }
+        };
+        self.flush()?;
+
+        result
+    }
+
+    /// Lists the registers, stack and heap slots, which differ between the memory of this interpreter and the other one
+    pub fn diff(&self, other: &Interpreter) -> Vec<StateDelta> {
+        self.memory.diff(&other.memory)
+    }
+
+    /// Captures the full state of the interpreter, so another one can run in the meantime
+    pub fn suspend(self) -> SuspendedInterpreter {
+        SuspendedInterpreter { interpreter: self }
+    }
+
+    fn run_until_halt(&mut self, max_steps: Option<usize>) -> Result<Type, ProgramError> {
+        let mut steps = 0;
+
+        loop {
+            // falling off the end of the program halts without executing an instruction, so it isn't a step
+            let past_end = self.program_pointer >= self.source_code.len();
+            if let Some(max_steps) = max_steps.filter(|max_steps| steps >= *max_steps && !past_end) {
+                return Err(ProgramError::StepLimitExceeded(max_steps));
+            }
+
+            if let StepOutcome::Halted(value) = self.step()? {
+                return Ok(value);
+            }
+
+            steps += 1;
+        }
+    }
+
+    /// Executes a single instruction
+    pub fn step(&mut self) -> Result<StepOutcome, ProgramError> {
+        let Some(command) = self.source_code.get(self.program_pointer) else {
+            return self.halt(Type::Integer(0));
+        };
+
+        let command = command.clone();
+        let index = self.program_pointer;
+
+        if let Some(limit) = self.gas_limit {
+            let cost = self.gas_costs.get(&command.mnemonic()).copied().unwrap_or(DEFAULT_GAS_COST);
+            if self.gas_used.saturating_add(cost) > limit {
+                return Err(self.runtime_error(ProgramError::OutOfGas { limit, used: self.gas_used }));
+            }
+
+            self.gas_used += cost;
+        }
+
+        let holding_value = self.execute(&command).map_err(|error| self.runtime_error(error))?;
+
+        if self.execution_counts.len() < self.source_code.len() {
+            self.execution_counts.resize(self.source_code.len(), 0);
+        }
+        self.execution_counts[index] += 1;
+
+        if let Some(StepCallback(callback)) = &mut self.step_callback {
+            callback(&command, &self.memory);
+        }
+
+        if let Some(holding_value) = holding_value {
+            return self.halt(holding_value);
+        }
+
+        self.program_pointer += 1;
+
+        if matches!(&command, Command::Syscall(JumpDestination::Label(label), _) if label == "yield") {
+            return Ok(StepOutcome::Yielded(index));
+        }
+
+        Ok(StepOutcome::Executed(index))
+    }
+
+    fn halt(&self, value: Type) -> Result<StepOutcome, ProgramError> {
+        if self.strict_frames && !self.memory.stack_frame.is_empty() {
+            return Err(ProgramError::LeakedStackFrames(self.memory.stack_frame.iter().map(|stack_frame| stack_frame.return_address).collect()));
+        }
+
+        Ok(StepOutcome::Halted(value))
+    }
+
+    /// Iterates over the outcome of every executed instruction. The iterator ends after the program
+    /// halted or failed, the output is flushed at this point
+    pub fn steps(&mut self) -> impl Iterator<Item = Result<StepOutcome, ProgramError>> + '_ {
+        let mut finished = false;
+
+        std::iter::from_fn(move || {
+            if finished {
+                return None;
+            }
+
+            let outcome = self.step();
+            if !matches!(outcome, Ok(StepOutcome::Executed(_) | StepOutcome::Yielded(_))) {
+                finished = true;
+
+                if let Err(error) = self.flush() {
+                    return Some(Err(error));
+                }
+            }
+
+            Some(outcome)
+        })
+    }
+
+    /// Runs the program in an interactive debugger. Before every instruction, its index and disassembly
+    /// are printed and commands are read line by line from the input:
+    ///
+    /// - `step` or an empty line executes the instruction
+    /// - `continue` runs the program until it halts, without asking again
+    /// - `print ADDRESS` prints the value at the address, e.g. `print rax` or `print sp[2]`
+    ///
+    /// Running out of input continues the program. The output is flushed afterward, even if the program failed
+    pub fn debug(&mut self, input: impl BufRead) -> Result<Type, ProgramError> {
+        let result = self.debug_until_halt(input);
+        self.flush()?;
+
+        result
+    }
+
+    fn debug_until_halt(&mut self, input: impl BufRead) -> Result<Type, ProgramError> {
+        let mut lines = input.lines();
+
+        loop {
+            if let Some(command) = self.source_code.get(self.program_pointer) {
+                self.memory.output.println(&format!("{}: {command}", self.program_pointer)).map_err(MemoryError::from)?;
+            }
+
+            // reads debugger commands until the instruction is executed
+            loop {
+                // the prompt has to be visible, before waiting for the next command
+                self.memory.output.flush().map_err(MemoryError::from)?;
+                let Some(line) = lines.next().transpose().map_err(MemoryError::from)? else {
+                    return self.run_until_halt(None);
+                };
+
+                let response = match line.split_whitespace().collect::<Vec<_>>()[..] {
+                    [] | ["step"] => break,
+                    ["continue"] => return self.run_until_halt(None),
+                    ["print", address] => match Address::from_str(address) {
+                        Ok(parsed) => match self.memory.get(&Assignment::Address(parsed)) {
+                            Ok(value) => format!("{address} = {}", value.with_radix(self.display_radix)),
+                            Err(error) => error.to_string()
+                        },
+                        Err(error) => error.to_string()
+                    },
+                    _ => format!("Unknown debugger command: {line}")
+                };
+
+                self.memory.output.println(&response).map_err(MemoryError::from)?;
+            }
+
+            if let StepOutcome::Halted(value) = self.step()? {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn execute(&mut self, command: &Command) -> Result<Option<Type>, ProgramError> {
+        // a tail call reuses the current stack frame, so the callee returns directly to the caller of the current frame
+        match self.tail_call_frames(command) {
+            Some(jumped_frames) => self.memory.stack_frame.truncate(self.memory.stack_frame.len() - jumped_frames),
+            None => command.execute(&mut self.memory, self.program_pointer)?
+        }
+
+        self.mutate(command)
+    }
+
+    /// Checks, if the command is a `call` into a register, which is immediately returned with `ret`.
+    /// The register is restored when the reused frame returns, so skipping the write into it isn't observable.
+    /// Frames entered with a jump don't restore registers, so they're dropped instead, if their return address is
+    /// followed by a `ret` of the same register as well, which would return the result again right away.
+    /// Returns the amount of frames to drop, before the frame below them is reused
+    fn tail_call_frames(&self, command: &Command) -> Option<usize> {
+        let Command::CallRet(destinations, _) = command else {
+            return None;
+        };
+
+        let [destination @ Address::Register(_)] = &destinations[..] else {
+            return None;
+        };
+
+        let returns_result = |program_pointer: usize| matches!(
+            self.source_code.get(program_pointer + 1),
+            Some(Command::Return(assignments)) if assignments[..] == [Assignment::Address(destination.clone())]
         );
 
-        if let Some(index) = potential_index {
-            self.program_pointer = index;
-            Ok(())
-        } else {
-            Err(ProgramError::LabelNotFound(target_label.to_string()))
+        if !returns_result(self.program_pointer) {
+            return None;
+        }
+
+        for (jumped_frames, stack_frame) in self.memory.stack_frame.iter().rev().enumerate() {
+            if !stack_frame.entered_with_jmp {
+                return Some(jumped_frames);
+            }
+
+            if !returns_result(stack_frame.return_address) {
+                return None;
+            }
+        }
+
+        None
+    }
+
+    /// Attaches the position of the current instruction to the error
+    fn runtime_error(&self, error: ProgramError) -> ProgramError {
+        ProgramError::Runtime {
+            instruction: self.program_pointer,
+            line: self.source_lines.get(self.program_pointer).copied(),
+            error: Box::new(error),
+        }
+    }
+
+    /// Replaces arithmetic instructions with constant operands by a `mov` of their result, as well as identities
+    /// like `add rax rbx 0`, if `rbx` is known to hold an integer. Nothing is folded, if arithmetic updates the flags,
+    /// since `mov` doesn't
+    pub fn fold_constants(&mut self) {
+        if self.memory.flag_arithmetic {
+            return;
+        }
+
+        // `mov pc` can continue at any instruction, so it's unknown which registers hold an integer
+        let computed_jumps = self.source_code.iter().any(|command| matches!(command, Command::Mov(Address::ProgramCounter, _)));
+        let mut integers = HashSet::new();
+
+        for command in &mut self.source_code {
+            let folded = command.fold_constants()
+                .or_else(|| command.fold_identity(&integers).filter(|_| !computed_jumps));
+
+            if let Some(folded) = folded {
+                *command = folded;
+            }
+            command.track_integers(&mut integers);
+        }
+    }
+
+    /// Disassembles every command, parses it again and checks, if the result equals the original command
+    pub fn verify_roundtrip(&self) -> Result<(), ProgramError> {
+        for (index, command) in self.source_code.iter().enumerate() {
+            let disassembly = command.to_string();
+
+            match Command::from_str(&disassembly) {
+                Ok(reparsed) if reparsed == *command => {},
+                _ => return Err(ProgramError::RoundTripMismatch(index, disassembly))
+            }
+        }
+
+        Ok(())
+    }
+
+    fn jump_to(&mut self, jump_destination: &JumpDestination) -> Result<(), ProgramError> {
+        self.program_pointer = jump_destination.resolve(self)?;
+        Ok(())
+    }
+
+    /// Reads the value a conditional jump is based on. Without an assignment, the flags are used.
+    /// Booleans count as 1 and 0 like the results of `sete` without `boolean_comparisons`, so `jne` branches on `true`
+    /// and `je` on `false`. Booleans have no order, the other jumps reject them
+    fn condition_value(&self, assignment: &Option<Assignment>, ordered: bool) -> Result<Option<isize>, ProgramError> {
+        match assignment {
+            Some(assignment) => match self.memory.get(assignment)? {
+                Type::Integer(value) => Ok(Some(value)),
+                Type::Boolean(value) if !ordered => Ok(Some(isize::from(value))),
+                rest @ Type::Boolean(_) => Err(MemoryError::from(OperationError::WrongType { expected: "Integer".to_string(), actual: format!("{rest}") }).into()),
+                _ => Ok(None)
+            },
+            None => Ok(self.memory.flags.sign_value())
+        }
+    }
+
+    /// Jumps, if the condition holds for the value the jump is based on. Otherwise, the stack frame the jump
+    /// pushed is popped again. Without a value, e.g. because the flags are cleared, the jump never branches
+    fn jump_if(&mut self, assignment: &Option<Assignment>, jump_destination: &JumpDestination, ordered: bool, condition: fn(isize) -> bool) -> Result<(), ProgramError> {
+        match self.condition_value(assignment, ordered)? {
+            Some(value) if condition(value) => self.jump_to(jump_destination),
+            _ => {
+                self.memory.stack_frame.pop();
+                Ok(())
+            }
         }
     }
 
     /// Resulting in new return_value, if holding
     pub fn mutate(&mut self, command: &Command) -> Result<Option<Type>, ProgramError> {
         match command {
-            Command::CallVoid(JumpDestination::Label(target_label)) | Command::CallRet(_, JumpDestination::Label(target_label)) | Command::Jmp(JumpDestination::Label(target_label)) => {
-                self.search_label_jump(target_label)?;
+            Command::CallVoid(jump_destination) | Command::CallRet(_, jump_destination) | Command::Jmp(jump_destination) => {
+                self.jump_to(jump_destination)?;
             },
-            Command::JumpLess(assignment, JumpDestination::Label(target_label)) => {
-                if let Type::Integer(value) = self.memory.get(assignment)? {
-                    if value == -1 {
-                        self.search_label_jump(target_label)?
-                    } else {
-                        self.memory.stack_frame.pop();
-                    }
+            Command::JumpLess(assignment, jump_destination) => self.jump_if(assignment, jump_destination, true, |value| value == -1)?,
+            Command::JumpGreater(assignment, jump_destination) => self.jump_if(assignment, jump_destination, true, |value| value == 1)?,
+            Command::JumpLessEqual(assignment, jump_destination) => self.jump_if(assignment, jump_destination, true, |value| value <= 0)?,
+            Command::JumpGreaterEqual(assignment, jump_destination) => self.jump_if(assignment, jump_destination, true, |value| value >= 0)?,
+            Command::JumpNotEqual(assignment, jump_destination) => self.jump_if(assignment, jump_destination, false, |value| value != 0)?,
+            Command::JumpEqual(assignment, jump_destination) => self.jump_if(assignment, jump_destination, false, |value| value == 0)?,
 
+            Command::Syscall(JumpDestination::Label(label), arguments) if label == "spawn" => {
+                match self.memory.syscall_arguments(arguments)?.swap_remove(0) {
+                    Type::String(name) => {
+                        let exit_code = self.spawn(&name)?;
+                        self.memory.set_register(Register::Rax, exit_code)
+                    },
+                    rest => return Err(MemoryError::from(OperationError::WrongType { expected: "String".to_string(), actual: format!("{rest}") }).into())
                 }
             },
-            Command::JumpGreater(assignment, JumpDestination::Label(target_label)) => {
-                if let Type::Integer(value) = self.memory.get(assignment)? {
-                    if value == 1 {
-                        self.search_label_jump(target_label)?
-                    } else {
-                        self.memory.stack_frame.pop();
-                    }
+            Command::Syscall(JumpDestination::Label(label), arguments) if label == "getenv" => {
+                let value = match self.memory.syscall_arguments(arguments)?.swap_remove(0) {
+                    Type::String(name) => match &self.env {
+                        Some(env) => env.get(&name).cloned(),
+                        None => std::env::var(&name).ok()
+                    },
+                    rest => return Err(MemoryError::from(OperationError::WrongType { expected: "String".to_string(), actual: format!("{rest}") }).into())
+                };
 
+                self.memory.set_register(Register::Rbx, value.map_or(Type::Untyped, Type::String));
+            },
+            Command::Syscall(JumpDestination::Label(label), _) if label == "dumpstack" => {
+                for line in pretty_print_stack(0, &self.memory.stack, &self.blank_stack_value(), self.compact_stack_print, self.display_radix) {
+                    self.memory.output.println(&line).map_err(MemoryError::from)?;
                 }
-            }
-            Command::JumpNotEqual(assignment, JumpDestination::Label(target_label)) => {
-                if let Type::Integer(value) = self.memory.get(assignment)? {
-                    if value != 0 {
-                        self.search_label_jump(target_label)?
-                    } else {
-                        self.memory.stack_frame.pop();
-                    }
-                }
-            }
-            Command::JumpEqual(assignment, JumpDestination::Label(target_label)) => {
-                if let Type::Integer(value) = self.memory.get(assignment)? {
-                    if value == 0 {
-                        self.search_label_jump(target_label)?
-                    } else {
-                        self.memory.stack_frame.pop();
-                    }
+            },
+            Command::Syscall(JumpDestination::Label(label), _) if label == "dumpregs" => {
+                for register in Register::ALL {
+                    self.memory.output.println(&format!("{register}: {}", self.memory.register(register).with_radix(self.display_radix))).map_err(MemoryError::from)?;
                 }
-            }
+            },
+            Command::JumpTable(assignment, jump_destinations) => {
+                let jump_destination = match self.memory.get(assignment)? {
+                    Type::Integer(index) if index >= 0 => jump_destinations.get(index as usize),
+                    Type::Integer(_) => None,
+                    rest => return Err(MemoryError::from(OperationError::WrongType { expected: "Integer".to_string(), actual: format!("{rest}") }).into())
+                };
 
-            Command::Return(assignment) => {
-                let value = self.memory.get(assignment)?;
+                if let Some(jump_destination) = jump_destination {
+                    self.jump_to(jump_destination)?
+                } else {
+                    self.memory.stack_frame.pop();
+                }
+            },
+            Command::Mov(Address::ProgramCounter, assignment) => {
+                match self.memory.get(assignment)? {
+                    Type::Integer(index) if index >= 0 && (index as usize) < self.source_code.len() => {
+                        self.program_pointer = index as usize;
+                    },
+                    Type::Integer(index) => return Err(ProgramError::ProgramCounterOutOfBounds(index)),
+                    rest => return Err(MemoryError::from(OperationError::WrongType { expected: "Integer".to_string(), actual: format!("{rest}") }).into())
+                }
+            },
+            Command::Return(assignments) => {
+                let values = assignments.iter().map(|assignment| self.memory.get(assignment)).collect::<Result<Vec<_>, _>>()?;
                 if self.memory.stack_frame.is_empty() {
-                    return Ok(Some(value));
+                    if values.len() != 1 {
+                        return Err(ProgramError::ReturnCountMismatch { expected: 1, actual: values.len() });
+                    }
+
+                    return Ok(values.into_iter().next());
                 } else if let Some(stack_frame) = self.memory.stack_frame.pop() {
+                    // without destinations, the return values are discarded
+                    if !stack_frame.destinations.is_empty() && stack_frame.destinations.len() != values.len() {
+                        return Err(ProgramError::ReturnCountMismatch { expected: stack_frame.destinations.len(), actual: values.len() });
+                    }
+
                     if !stack_frame.entered_with_jmp {
-                        (self.memory.rax, self.memory.rbx, self.memory.rcx) = stack_frame.register_state;
+                        self.memory.restore_register_state(stack_frame.register_state);
                     }
 
-                    if let Some(destination) = stack_frame.destination {
-                        self.memory.set(&destination, value)?;
+                    for (destination, value) in stack_frame.destinations.iter().zip(values) {
+                        self.memory.set(destination, value)?;
                     }
 
                     self.program_pointer = stack_frame.return_address;
@@ -200,21 +960,658 @@ impl Interpreter {
                 if self.memory.stack_frame.is_empty() {
                     return Ok(Some(Type::Integer(0)))
                 } else if let Some(stack_frame) = self.memory.stack_frame.pop() {
-                    assert_eq!(stack_frame.destination, None);
+                    if !stack_frame.destinations.is_empty() {
+                        return Err(ProgramError::LeaveWithoutValue(stack_frame.destinations));
+                    }
 
                     if !stack_frame.entered_with_jmp {
-                        (self.memory.rax, self.memory.rbx, self.memory.rcx) = stack_frame.register_state;
+                        self.memory.restore_register_state(stack_frame.register_state);
                     }
 
                     self.program_pointer = stack_frame.return_address;
                 }
             },
-            Command::Compare(_, _, _) |
+            Command::SaveContext(name) => {
+                self.contexts.insert(name.clone(), (self.memory.register_state(), self.memory.flags.clone()));
+            },
+            Command::LoadContext(name) => {
+                let (register_state, flags) = self.contexts.get(name).cloned().ok_or_else(|| ProgramError::ContextNotFound(name.clone()))?;
+                self.memory.restore_register_state(register_state);
+                self.memory.flags = flags;
+            },
+            Command::HaltIf(value, expected) => {
+                let value = self.memory.get(value)?;
+                if value == self.memory.get(expected)? {
+                    return Ok(Some(value));
+                }
+            },
+            Command::Unwind(frames) => {
+                let depth = self.memory.stack_frame.len();
+                if *frames > depth {
+                    return Err(ProgramError::UnwindOutOfBounds { frames: *frames, depth });
+                }
+
+                let outermost_frame = self.memory.stack_frame.drain(depth - frames..).next();
+                if let Some(stack_frame) = outermost_frame {
+                    if !stack_frame.entered_with_jmp {
+                        self.memory.restore_register_state(stack_frame.register_state);
+                    }
+
+                    self.program_pointer = stack_frame.return_address;
+                }
+            },
+            Command::Compare(_, _, _)           | Command::StringEqual(_, _, _) |
             Command::LoadEffectiveAddress(_, _) | Command::Mov(_, _) |
             Command::Add(_, _, _)               | Command::Sub(_, _, _) |
-            Command::Label(_)                   | Command::Syscall(_) => {}
+            Command::Label(_, _)                | Command::Syscall(_, _) |
+            Command::SetEqual(_)                | Command::SetNotEqual(_) |
+            Command::SetLess(_)                 | Command::SetGreater(_) |
+            Command::TypeOf(_, _)               | Command::RotateRegisters |
+            Command::Append(_, _)               | Command::LoadIndexed(_, _, _) |
+            Command::ArrayNew(_, _)             | Command::ArrayGet(_, _, _) |
+            Command::ArraySet(_, _, _)          | Command::MoveEqual(_, _) |
+            Command::MoveNotEqual(_, _)         | Command::MoveLess(_, _) |
+            Command::MoveGreater(_, _)          | Command::Data(_, _) |
+            Command::ClearFlags                 | Command::StringData(_, _) |
+            Command::GetByte(_, _, _)           | Command::AddressToInteger(_, _) |
+            Command::IntegerToAddress(_, _)     | Command::Mul(_, _, _) |
+            Command::Div(_, _, _)               | Command::Mod(_, _, _) |
+            Command::And(_, _, _)               | Command::Or(_, _, _) |
+            Command::Xor(_, _, _)               | Command::Neg(_, _) |
+            Command::Push(_)                    | Command::Pop(_) |
+            Command::Nop => {}
         }
 
         Ok(None)
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use crate::test_utils::{captured, text};
+    use super::*;
+
+    #[test]
+    fn parsed_programs_survive_the_round_trip() {
+        for source in [include_str!("assembly.asm"), include_str!("array_init.asm"), include_str!("join_strings.asm"), include_str!("test.asm")] {
+            let interpreter = Interpreter::from_str(source).unwrap();
+            assert_eq!(interpreter.program_text.as_deref(), Some(source));
+            interpreter.verify_roundtrip().unwrap();
+        }
+    }
+
+    #[test]
+    fn mis_disassembled_command_fails_the_round_trip() {
+        let mut interpreter = Interpreter::from_str("mov rax 5\nje done\ndone:\nleave").unwrap();
+        interpreter.source_code[0] = Command::Mov(Address::Register(Register::Rax), Assignment::Value(Type::Untyped));
+
+        assert!(matches!(interpreter.verify_roundtrip(), Err(ProgramError::RoundTripMismatch(0, _))));
+    }
+
+    #[test]
+    fn runtime_errors_report_instruction_and_line() {
+        let mut interpreter = Interpreter::from_str("mov rax 1\n\n; out of bounds\nmov sp[100] 5\nret 0").unwrap();
+
+        assert_eq!(interpreter.run().unwrap_err().to_string(), "at instruction 1 (line 4): Cannot write at: 0x100");
+    }
+
+    #[test]
+    fn mov_pc_transfers_control_to_the_instruction_index() {
+        // index 3 is the label `target`, so execution continues right after it
+        let mut interpreter = Interpreter::from_str("mov rax 1\nmov pc 3\nret 5\ntarget:\nret 9").unwrap();
+        assert_eq!(interpreter.run().unwrap(), Type::Integer(9));
+
+        let mut interpreter = Interpreter::from_str("mov pc 30\nret 5").unwrap();
+        match interpreter.run() {
+            Err(ProgramError::Runtime { error, .. }) => assert!(matches!(*error, ProgramError::ProgramCounterOutOfBounds(30))),
+            other => panic!("expected an out of bounds program counter, got {other:?}")
+        }
+    }
+
+    #[test]
+    fn lenient_parsing_records_bad_lines_and_keeps_the_rest() {
+        let (mut interpreter, errors) = Interpreter::from_str_lenient("mov rax 5\nfoo bar baz\nret rax");
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, 2);
+        assert_eq!(interpreter.source_code[1], Command::Nop);
+        assert_eq!(interpreter.run().unwrap(), Type::Integer(5));
+    }
+
+    #[test]
+    fn compact_stack_print_merges_identical_values() {
+        let mut stack = vec![Type::Integer(0); 10];
+        stack.extend([Type::Integer(1), Type::Untyped, Type::Untyped]);
+
+        assert_eq!(pretty_print_stack(0, &stack, &Type::Untyped, true, Radix::Decimal), vec!["0..9: Integer '0'", "10: Integer '1'", "11..12: Untyped"]);
+        assert_eq!(pretty_print_stack(0, &stack, &Type::Untyped, false, Radix::Decimal).len(), 12);
+    }
+
+    #[test]
+    fn void_call_of_a_returning_routine_warns() {
+        let interpreter = Interpreter::from_str("call routine\nret 0\nroutine:\n    ret 5").unwrap();
+        let warnings = interpreter.semantic_warnings().unwrap();
+        assert!(matches!(warnings.as_slice(), [SemanticError::DiscardedReturn { label }] if label == "routine"));
+
+        let interpreter = Interpreter::from_str("call routine\nret 0\nroutine:\n    leave").unwrap();
+        assert!(interpreter.semantic_warnings().unwrap().is_empty());
+    }
+
+    #[test]
+    fn output_is_flushed_when_the_program_halts() {
+        let (mut interpreter, output) = captured("mov rax \"first\"\nsyscall prints\nmov rax \"second\"\nsyscall prints\nret 0");
+        interpreter.run().unwrap();
+
+        assert_eq!(text(&output), "first\nsecond\n");
+    }
+
+    #[test]
+    fn steps_end_when_the_program_halts() {
+        let mut interpreter = Interpreter::from_str("mov rax 1\nadd rax rax 2\nret rax").unwrap();
+        let outcomes = interpreter.steps().collect::<Result<Vec<_>, _>>().unwrap();
+
+        assert_eq!(outcomes, vec![StepOutcome::Executed(0), StepOutcome::Executed(1), StepOutcome::Halted(Type::Integer(3))]);
+    }
+
+    #[test]
+    fn jmptable_dispatches_by_index_and_falls_through_out_of_range() {
+        for (index, expected) in [(0, 10), (1, 11), (2, 12), (3, 0)] {
+            let source = format!("mov rbx 0\nmov rax {index}\njmptable rax l0 l1 l2\nret rbx\nl0:\nmov rbx 10\nleave\nl1:\nmov rbx 11\nleave\nl2:\nmov rbx 12\nleave");
+            let mut interpreter = Interpreter::from_str(&source).unwrap();
+
+            interpreter.semantic_check().unwrap();
+            assert_eq!(interpreter.run().unwrap(), Type::Integer(expected));
+            assert!(interpreter.memory.stack_frame.is_empty());
+        }
+
+        let interpreter = Interpreter::from_str("jmptable rax l0 missing\nl0:\nleave").unwrap();
+        assert!(interpreter.semantic_check().is_err());
+    }
+
+    #[test]
+    fn run_unchecked_skips_the_semantic_check() {
+        let source = "jmp forever\nret 3\nforever:\nmov rax 1\nother:\nleave";
+
+        assert!(matches!(Interpreter::from_str(source).unwrap().run(), Err(ProgramError::Semantic(_))));
+        assert_eq!(Interpreter::from_str(source).unwrap().run_unchecked().unwrap(), Type::Integer(3));
+    }
+
+    #[test]
+    fn stack_dump_honors_the_display_radix() {
+        let mut interpreter = Interpreter::from_str("mov sp[2] 255\nret 0").unwrap();
+        interpreter.run().unwrap();
+        interpreter.display_radix = Radix::Hexadecimal;
+
+        assert!(interpreter.to_string().contains("2: Integer '0xff'"));
+    }
+
+    #[test]
+    fn spawned_child_returns_its_exit_value_into_rax() {
+        let (mut interpreter, output) = captured("mov rbx 1\nmov rax \"child\"\nsyscall spawn\nadd rax rax rbx\nret rax");
+        interpreter.register_program("child", "mov rax \"in child\"\nsyscall prints\nret 41").unwrap();
+
+        assert_eq!(interpreter.run().unwrap(), Type::Integer(42));
+        assert_eq!(text(&output), "in child\n");
+    }
+
+    #[test]
+    fn spawning_recursively_is_limited() {
+        let mut interpreter = Interpreter::from_str("mov rax \"loop\"\nsyscall spawn\nret 0").unwrap();
+        interpreter.register_program("loop", "mov rax \"loop\"\nsyscall spawn\nret 0").unwrap();
+
+        let error = interpreter.run().unwrap_err().to_string();
+        assert!(error.ends_with(&format!("Cannot spawn more than {MAX_SPAWN_DEPTH} nested programs")), "{error}");
+    }
+
+    #[test]
+    fn run_bounded_returns_the_value_of_a_terminating_program() {
+        let mut interpreter = Interpreter::from_str("mov rax 1\nadd rax rax 1\nret rax").unwrap();
+        assert_eq!(interpreter.run_bounded(3).unwrap(), Type::Integer(2));
+
+        let mut interpreter = Interpreter::from_str("mov rax 1\nadd rax rax 1\nret rax").unwrap();
+        assert!(matches!(interpreter.run_bounded(2), Err(ProgramError::StepLimitExceeded(2))));
+    }
+
+    #[test]
+    fn falling_off_the_end_is_not_a_step() {
+        let mut interpreter = Interpreter::from_str("mov rax 1\nmov rbx 2").unwrap();
+        assert_eq!(interpreter.run_bounded(2).unwrap(), Type::Integer(0));
+    }
+
+    #[test]
+    fn run_bounded_stops_a_non_terminating_program() {
+        let mut interpreter = Interpreter::from_str("loop:\nmov pc 0\n").unwrap();
+        assert_eq!(interpreter.run_bounded(100).unwrap_err().to_string(), "The program didn't halt within 100 steps");
+    }
+
+    #[test]
+    fn ret_returns_slots_references_and_values_to_the_caller() {
+        for (source, expected) in [
+            ("call rbx f\nret rbx\nf:\nmov sp[3] 42\nret sp[3]", 42),
+            ("call rbx f\nret rbx\nf:\nmov sp[2] 17\nlea rax sp[2]\nret [rax]", 17),
+            ("call sp[0] f\nret sp[0]\nf:\nret 5", 5),
+        ] {
+            assert_eq!(Interpreter::from_str(source).unwrap().run().unwrap(), Type::Integer(expected), "{source}");
+        }
+    }
+
+    #[test]
+    fn step_callback_sees_every_executed_instruction() {
+        let seen = Rc::new(RefCell::new(vec![]));
+        let mut interpreter = Interpreter::from_str("mov rax 1\nadd rax rax 2\nret rax").unwrap();
+
+        let recorder = seen.clone();
+        interpreter.on_step(move |command, memory| recorder.borrow_mut().push((command.to_string(), memory.register(Register::Rax))));
+        interpreter.run().unwrap();
+
+        assert_eq!(*seen.borrow(), vec![
+            ("mov rax 1".to_string(), Type::Integer(1)),
+            ("add rax rax 2".to_string(), Type::Integer(3)),
+            ("ret rax".to_string(), Type::Integer(3)),
+        ]);
+    }
+
+    #[test]
+    fn execution_counts_find_the_hot_loop() {
+        let mut interpreter = Interpreter::from_str("mov rax 0\nloop:\nadd rax rax 1\ncmp rax 10\njl loop\nret rax").unwrap();
+        assert_eq!(interpreter.run().unwrap(), Type::Integer(10));
+
+        let counts = interpreter.execution_counts();
+        assert_eq!(counts[0], 1);
+        assert_eq!(counts[2], 10);
+        assert_eq!(counts[3], 10);
+    }
+
+    #[test]
+    fn dump_syscalls_print_registers_and_stack() {
+        let (mut interpreter, output) = captured("mov rax 5\nmov rbx \"hi\"\nmov sp[2] 7\nsyscall dumpregs\nsyscall dumpstack\nret 0");
+        interpreter.run().unwrap();
+
+        let text = text(&output);
+        assert!(text.starts_with("rax: Integer '5'\nrbx: String 'hi'\nrcx: Untyped\nrdx: Untyped\n"), "{text}");
+        assert!(text.contains("0..1: Untyped\n2: Integer '7'\n"), "{text}");
+    }
+
+    #[test]
+    fn routine_returns_two_values_into_two_registers() {
+        let mut interpreter = Interpreter::from_str("call rax,rcx divmod\nsub rax rax rcx\nret rax\ndivmod:\nret 7 3").unwrap();
+
+        assert_eq!(interpreter.source_code[0].to_string(), "call rax,rcx divmod");
+        assert_eq!(interpreter.source_code[4].to_string(), "ret 7 3");
+        assert_eq!(interpreter.run().unwrap(), Type::Integer(4));
+    }
+
+    #[test]
+    fn returning_too_few_values_fails() {
+        let mut interpreter = Interpreter::from_str("call rax,rcx f\nret rax\nf:\nret 7").unwrap();
+
+        assert_eq!(interpreter.run().unwrap_err().to_string(), "at instruction 3 (line 4): Expected 2 return values, but 1 were returned");
+    }
+
+    #[test]
+    fn yielding_interpreters_interleave() {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let coroutine = |name: &str| {
+            let source = format!("mov rax \"{name}1\"\nsyscall prints\nsyscall yield\nmov rax \"{name}2\"\nsyscall prints\nsyscall yield\nret 0");
+            Interpreter::from_str(&source).unwrap().with_output(output.clone()).suspend()
+        };
+
+        let mut queue = std::collections::VecDeque::from([coroutine("a"), coroutine("b")]);
+        let mut halted = 0;
+        while let Some(suspended) = queue.pop_front() {
+            let mut interpreter = suspended.resume();
+            match interpreter.run_until_yield().unwrap() {
+                StepOutcome::Yielded(_) => queue.push_back(interpreter.suspend()),
+                StepOutcome::Halted(_) => halted += 1,
+                StepOutcome::Executed(index) => panic!("run_until_yield stopped after instruction {index}")
+            }
+        }
+
+        assert_eq!(halted, 2);
+        assert_eq!(text(&output), "a1\nb1\na2\nb2\n");
+    }
+
+    #[test]
+    fn zeroed_stack_reads_unwritten_slots_as_zero() {
+        let mut interpreter = Interpreter::from_str("add rax sp[0] 1\nmov sp[3] 5\nret rax").unwrap().with_zeroed_stack();
+        assert_eq!(interpreter.run().unwrap(), Type::Integer(1));
+
+        let dump = interpreter.to_string();
+        assert!(dump.contains("\"0..2: Integer '0'\", \"3: Integer '5'\", \"4..63: Integer '0'\""), "{dump}");
+    }
+
+    #[test]
+    fn leaving_a_call_which_expects_a_value_fails() {
+        let mut interpreter = Interpreter::from_str("call rax f\nret rax\nf:\nleave\nret 1").unwrap();
+
+        assert_eq!(interpreter.run().unwrap_err().to_string(), "at instruction 3 (line 4): The call returning into rax expects a value, but was left with `leave` instead of `ret`");
+    }
+
+    #[test]
+    fn diff_against_a_snapshot_lists_the_changed_slot() {
+        let mut interpreter = Interpreter::from_str("mov sp[4] 9\nret 0").unwrap();
+        let snapshot = interpreter.memory.snapshot();
+        interpreter.step().unwrap();
+
+        let deltas = snapshot.diff(&interpreter.memory);
+        assert_eq!(deltas, vec![StateDelta { address: Address::StackPointer(4), before: Type::Untyped, after: Type::Integer(9) }]);
+        assert_eq!(deltas[0].to_string(), "sp[4]: Untyped -> Integer '9'");
+    }
+
+    #[test]
+    fn diff_between_interpreters_compares_their_memory() {
+        let before = Interpreter::from_str("mov rax 1\nret 0").unwrap();
+        let mut after = Interpreter::from_str("mov rax 1\nret 0").unwrap();
+        after.step().unwrap();
+
+        assert_eq!(before.diff(&after), vec![StateDelta { address: Address::Register(Register::Rax), before: Type::Untyped, after: Type::Integer(1) }]);
+    }
+
+    #[test]
+    fn stack_ranges_match_the_slot_indices() {
+        let mut stack = vec![Type::Untyped; 10];
+        stack[3] = Type::Integer(1);
+        stack[7] = Type::String("a".to_string());
+
+        assert_eq!(pretty_print_stack(0, &stack, &Type::Untyped, false, Radix::Decimal), vec!["0..2: Untyped", "3: Integer '1'", "4..6: Untyped", "7: String 'a'", "8..9: Untyped"]);
+        assert_eq!(pretty_print_stack(4, &stack, &Type::Untyped, false, Radix::Decimal), vec!["4..6: Untyped", "7: String 'a'", "8..9: Untyped"]);
+    }
+
+    #[test]
+    fn getenv_reads_from_the_injected_environment() {
+        let env = HashMap::from([("NAME".to_string(), "asm".to_string())]);
+        let mut interpreter = Interpreter::from_str("mov rax \"NAME\"\nsyscall getenv\nmov rcx rbx\nsyscall getenv \"MISSING\"\nret rcx").unwrap().with_env(env);
+
+        assert_eq!(interpreter.run().unwrap(), Type::String("asm".to_string()));
+        assert_eq!(interpreter.memory.register(Register::Rbx), Type::Untyped);
+    }
+
+    #[test]
+    fn getenv_rejects_a_non_string_name() {
+        let mut interpreter = Interpreter::from_str("syscall getenv 5\nret 0").unwrap().with_env(HashMap::new());
+
+        match interpreter.run() {
+            Err(ProgramError::Runtime { error, .. }) => assert!(matches!(*error, ProgramError::Memory(MemoryError::OperationError(OperationError::WrongType { .. })))),
+            other => panic!("expected a wrong type, got {other:?}")
+        }
+    }
+
+    #[test]
+    fn folding_keeps_the_result_of_the_program() {
+        let source = "mov rbx 5\nadd rax rbx 0\nmul rcx rbx 0\nmul rdx 1 rbx\nadd rax rax rdx\nret rax";
+        let mut interpreter = Interpreter::from_str(source).unwrap();
+        interpreter.fold_constants();
+
+        let folded = interpreter.source_code.iter().map(|command| command.to_string()).collect::<Vec<_>>();
+        assert_eq!(folded, ["mov rbx 5", "mov rax rbx", "mov rcx 0", "mov rdx rbx", "add rax rax rdx", "ret rax"]);
+        assert_eq!(interpreter.run().unwrap(), Interpreter::from_str(source).unwrap().run().unwrap());
+    }
+
+    #[test]
+    fn identities_of_unknown_types_are_not_folded() {
+        let source = "mov rbx \"s\"\nadd rax rbx 0\nmov rcx 2\nf:\nmul rdx rcx 0\nret rax";
+        let mut interpreter = Interpreter::from_str(source).unwrap();
+        interpreter.fold_constants();
+        assert_eq!(interpreter.source_code, Interpreter::from_str(source).unwrap().source_code);
+
+        let source = "mov rcx 2\nmul rdx rcx 0\nmov pc 0";
+        let mut interpreter = Interpreter::from_str(source).unwrap();
+        interpreter.fold_constants();
+        assert_eq!(interpreter.source_code, Interpreter::from_str(source).unwrap().source_code);
+    }
+
+    #[test]
+    fn constants_are_folded() {
+        let mut interpreter = Interpreter::from_str("add rax 2 3\nsub rbx \"abc\" \"b\"\nsub sp[0] \"a\" 1\nret rax").unwrap();
+        interpreter.fold_constants();
+
+        let folded = interpreter.source_code.iter().map(|command| command.to_string()).collect::<Vec<_>>();
+        assert_eq!(folded, ["mov rax 5", "mov rbx \"ac\"", "sub sp[0] \"a\" 1", "ret rax"]);
+    }
+
+    #[test]
+    fn unwind_returns_to_the_grand_caller_with_its_registers() {
+        let source = "mov rax 1\ncall outer\nret rax\nouter:\nmov rax 2\ncall inner\nmov rax 99\nleave\ninner:\nmov rax 3\nunwind 2\nleave";
+        let mut interpreter = Interpreter::from_str(source).unwrap();
+
+        assert_eq!(interpreter.run().unwrap(), Type::Integer(1));
+        assert!(interpreter.memory.stack_frame.is_empty());
+    }
+
+    #[test]
+    fn unwinding_more_frames_than_exist_fails() {
+        let mut interpreter = Interpreter::from_str("call f\nret 0\nf:\nunwind 2\nleave").unwrap();
+
+        let error = interpreter.run().unwrap_err().to_string();
+        assert!(error.ends_with("Cannot unwind 2 stack frames, only 1 exist"), "{error}");
+        assert!(Interpreter::from_str("unwind 0").is_err());
+    }
+
+    #[test]
+    fn jump_which_doesnt_branch_leaves_no_stack_frame() {
+        for setup in ["mov rax \"s\"\nje rax skip", "clrflags\nje skip"] {
+            let (mut interpreter, output) = captured(&format!("{setup}\nsyscall prints \"once\"\nret 0\nskip:\nleave"));
+
+            assert_eq!(interpreter.run().unwrap(), Type::Integer(0));
+            assert_eq!(text(&output), "once\n", "{setup}");
+        }
+    }
+
+    #[test]
+    fn string_symbols_are_loaded_and_read_only() {
+        let (mut interpreter, output) = captured(".string msg \"hello world\"\nmov rax msg\nsyscall prints\nsyscall prints msg\nret rax");
+        assert_eq!(interpreter.source_code[0].to_string(), ".string msg \"hello world\"");
+        assert_eq!(interpreter.run().unwrap(), Type::String("hello world".to_string()));
+        assert_eq!(text(&output), "hello world\nhello world\n");
+
+        let mut interpreter = Interpreter::from_str(".string msg \"hello\"\nmov msg 5\nret 0").unwrap();
+        match interpreter.run() {
+            Err(ProgramError::Runtime { instruction: 1, error, .. }) => assert!(matches!(*error, ProgramError::Memory(MemoryError::ReadOnly(Address::Symbol(_))))),
+            other => panic!("expected a read-only error, got {other:?}")
+        }
+    }
+
+    #[test]
+    fn strings_can_be_used_before_their_definition() {
+        assert_eq!(Interpreter::from_str("mov rax msg\nret rax\n.string msg \"late\"").unwrap().run().unwrap(), Type::String("late".to_string()));
+    }
+
+    #[test]
+    fn unknown_symbols_are_rejected_when_parsing() {
+        let error = Interpreter::from_str("mov rbx 1\nmov rax rxa\nret rax").unwrap_err().to_string();
+        assert!(error.contains("Unknown symbol: rxa in line 2"), "{error}");
+
+        let (mut interpreter, errors) = Interpreter::from_str_lenient("mov rax 5\nfoo bar baz\nmov rbx rxa\nret rax");
+        assert_eq!(errors.iter().map(|(line_number, _)| *line_number).collect::<Vec<_>>(), vec![2, 3]);
+        assert_eq!(interpreter.source_code[2], Command::Nop);
+        assert_eq!(interpreter.run().unwrap(), Type::Integer(5));
+    }
+
+    #[test]
+    fn haltif_halts_only_on_equal_values() {
+        let source = |rax: isize| format!("mov rax {rax}\nhaltif rax 0\nmov rax 42\nret rax");
+        assert_eq!(Interpreter::from_str(&source(0)).unwrap().run().unwrap(), Type::Integer(0));
+        assert_eq!(Interpreter::from_str(&source(3)).unwrap().run().unwrap(), Type::Integer(42));
+        assert_eq!(Interpreter::from_str("haltif 1 2").unwrap().source_code[0].to_string(), "haltif 1 2");
+    }
+
+    #[test]
+    fn tail_calls_run_in_constant_frame_depth() {
+        let depth = |source: &str| {
+            let mut interpreter = Interpreter::from_str(source).unwrap();
+            let max_depth = Rc::new(Cell::new(0));
+            let observed_depth = max_depth.clone();
+            interpreter.on_step(move |_, memory| observed_depth.set(observed_depth.get().max(memory.stack_frame.len())));
+            (interpreter.run_unchecked().unwrap(), max_depth.get())
+        };
+
+        // the accumulator recurses from behind a conditional jump, whose frame returns the result right away
+        let accumulator = "mov rax 0\nmov rbx 1000\ncall rax sum\nret rax\nsum:\ncmp rbx 0\njg recurse\nret rax\nrecurse:\nadd rax rax rbx\nsub rbx rbx 1\ncall rax sum\nret rax";
+        assert_eq!(depth(accumulator), (Type::Integer(500500), 2));
+
+        // the frame of the jump continues with other instructions, so it has to stay
+        let continued = "mov rax 0\nmov rbx 3\ncall rax sum\nret rax\nsum:\ncmp rbx 0\njg recurse\nadd rax rax 100\nret rax\nrecurse:\nadd rax rax rbx\nsub rbx rbx 1\ncall rax sum\nret rax";
+        assert_eq!(depth(continued), (Type::Integer(406), 7));
+
+        let not_returned = "call rax f\nret rax\nf:\ncall rax g\nadd rax rax 1\nret rax\ng:\nret 1";
+        assert_eq!(depth(not_returned), (Type::Integer(2), 2));
+    }
+
+    #[test]
+    fn edges_contain_back_and_forward_jumps() {
+        let interpreter = Interpreter::from_str("mov rax 0\nloop:\nadd rax rax 1\ncmp rax 3\njle loop\njmp done\ndone:\nret rax\nmov pc rax").unwrap();
+        // 0 mov, 1 loop:, 2 add, 3 cmp, 4 jle, 5 jmp, 6 done:, 7 ret, 8 mov pc
+        assert_eq!(interpreter.edges(), vec![(4, 1), (5, 6)]);
+    }
+
+    #[test]
+    fn strict_frames_flag_leaked_frames() {
+        let source = "jmp finish\nfinish:\nmov rax 1";
+        assert_eq!(Interpreter::from_str(source).unwrap().run_unchecked().unwrap(), Type::Integer(0));
+
+        match Interpreter::from_str(source).unwrap().with_strict_frames().run_unchecked() {
+            Err(ProgramError::LeakedStackFrames(return_addresses)) => assert_eq!(return_addresses, vec![0]),
+            other => panic!("expected leaked stack frames, got {other:?}")
+        }
+
+        let mut interpreter = Interpreter::from_str("call f\nret 3\nf:\nleave").unwrap().with_strict_frames();
+        assert_eq!(interpreter.run().unwrap(), Type::Integer(3));
+    }
+
+    #[test]
+    fn gas_costs_accumulate_per_mnemonic() {
+        let mut interpreter = Interpreter::from_str("mov rax 2\nmul rax rax 3\nmul rax rax 3\nret rax").unwrap()
+            .with_gas_limit(100)
+            .with_gas_cost("mul", 5);
+
+        assert_eq!(interpreter.run().unwrap(), Type::Integer(18));
+        assert_eq!(interpreter.gas_used, 1 + 5 + 5 + 1);
+    }
+
+    #[test]
+    fn running_out_of_gas_aborts_a_loop() {
+        let mut interpreter = Interpreter::from_str("mov rax 0\nloop:\nadd rax rax 1\ncmp rax 1000000\njl loop\nret rax").unwrap()
+            .with_gas_limit(50)
+            .with_gas_cost("ret", u64::MAX);
+
+        match interpreter.run() {
+            Err(ProgramError::Runtime { error, .. }) => assert!(matches!(*error, ProgramError::OutOfGas { limit: 50, used } if used <= 50)),
+            other => panic!("expected to run out of gas, got {other:?}")
+        }
+        assert!(interpreter.gas_used <= 50);
+    }
+
+    #[test]
+    fn contexts_restore_registers_and_flags() {
+        let mut interpreter = Interpreter::from_str("mov rax 1\nmov rbx \"b\"\nmov rcx 'c'\ncmp 1 2\nsavectx main\nmov rax 9\nmov rbx 9\nmov rdx 9\ncmp 2 1\nloadctx main\nret 0").unwrap();
+        interpreter.run().unwrap();
+
+        assert_eq!(Register::ALL.map(|register| interpreter.memory.register(register)), [Type::Integer(1), Type::String("b".to_string()), Type::Char('c'), Type::Untyped]);
+        assert_eq!(interpreter.memory.flags.ordering, Some(std::cmp::Ordering::Less));
+
+        match Interpreter::from_str("loadctx missing\nret 0").unwrap().run() {
+            Err(ProgramError::Runtime { error, .. }) => assert!(matches!(*error, ProgramError::ContextNotFound(name) if name == "missing")),
+            other => panic!("expected a missing context, got {other:?}")
+        }
+    }
+
+    #[test]
+    fn debugger_steps_prints_and_continues() {
+        let (mut interpreter, output) = captured("mov rax 5\nadd rax rax 1\nmov rbx 2\nret rax");
+        let result = interpreter.debug(std::io::Cursor::new("step\nprint rax\n\nprint rax\nfoo\ncontinue\n"));
+
+        assert_eq!(result.unwrap(), Type::Integer(6));
+        assert_eq!(text(&output), "0: mov rax 5\n1: add rax rax 1\nrax = Integer '5'\n2: mov rbx 2\nrax = Integer '6'\nUnknown debugger command: foo\n");
+    }
+
+    #[test]
+    fn debugger_flushes_before_reading_commands() {
+        struct Flushes(Rc<Cell<usize>>);
+
+        impl Write for Flushes {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                self.0.set(self.0.get() + 1);
+                Ok(())
+            }
+        }
+
+        let flushes = Rc::new(Cell::new(0));
+        let mut interpreter = Interpreter::from_str("mov rax 5\nret rax").unwrap().with_output(Rc::new(RefCell::new(Flushes(flushes.clone()))));
+        interpreter.debug(std::io::Cursor::new("step\nprint rax\ncontinue\n")).unwrap();
+
+        // once before each of the three commands and once after halting
+        assert_eq!(flushes.get(), 4);
+    }
+
+    #[test]
+    fn declared_return_types_are_checked() {
+        let source = "call rax name\nret rax\nname -> Integer:\nret \"five\"";
+        assert_eq!(Interpreter::from_str(source).unwrap().source_code[2].to_string(), "name -> Integer:");
+        match Interpreter::from_str(source).unwrap().semantic_check() {
+            Err(ProgramError::Semantic(SemanticError::ReturnTypeMismatch { label, expected, actual })) => {
+                assert_eq!((label.as_str(), expected.as_str(), actual), ("name", "Integer", Type::String("five".to_string())));
+            },
+            other => panic!("expected a return type mismatch, got {other:?}")
+        }
+
+        let source = "call rax name\nret rax\nname -> Integer:\nmov rbx \"five\"\nret 5";
+        assert_eq!(Interpreter::from_str(source).unwrap().run().unwrap(), Type::Integer(5));
+
+        let mismatch = |source: &str| match Interpreter::from_str(source).unwrap().semantic_check() {
+            Err(ProgramError::Semantic(error @ (SemanticError::ReturnTypeMismatch { .. } | SemanticError::CallTypeMismatch { .. }))) => Some(error.to_string()),
+            Ok(()) => None,
+            Err(error) => panic!("expected a return type mismatch, got {error:?}")
+        };
+
+        let register = "call rax name\nret rax\nname -> Integer:\nmov rax \"x\"\nmov rbx rax\ncmp rcx 1\nret rbx";
+        assert_eq!(mismatch(register).unwrap(), "The label 'name' is declared to return Integer, but returns String 'x'");
+        assert_eq!(mismatch("call rax name\nret rax\nname -> Integer:\nmov rax \"x\"\nadd rax rax 1\nret rax"), None);
+        assert_eq!(mismatch("call rax name\nret rax\nname -> Integer:\nmov rax \"x\"\nsyscall time\nret rax"), None);
+
+        let call = "call rax outer\nret rax\nouter -> String:\ncall rbx inner\nret rbx\ninner -> Integer:\nret 5";
+        assert_eq!(mismatch(call).unwrap(), "The label 'outer' is declared to return String, but returns the Integer returned by 'inner'");
+        assert_eq!(mismatch(&call.replace("outer -> String", "outer -> Integer")), None);
+
+        assert!(Interpreter::from_str("name -> Number:").unwrap_err().to_string().contains("Unknown return type Number"));
+        assert!(Interpreter::from_str("name -> Integer").unwrap_err().to_string().contains("must end with ':'"));
+    }
+
+    #[test]
+    fn rdx_survives_a_call() {
+        let mut interpreter = Interpreter::from_str("mov rdx 4\ncall rax f\nadd rax rax rdx\nret rax\nf:\nmov rdx 100\nret 1").unwrap();
+        assert_eq!(interpreter.source_code[0].to_string(), "mov rdx 4");
+        assert_eq!(interpreter.run().unwrap(), Type::Integer(5));
+        assert_eq!(interpreter.memory.register(Register::Rdx), Type::Integer(4));
+    }
+
+    #[test]
+    fn calls_restore_every_register() {
+        let mut interpreter = Interpreter::from_str("mov rax 1\nmov rbx 2\nmov rcx 3\nmov rdx 4\ncall f\nret 0\nf:\nmov rax 9\nmov rbx 9\nmov rcx 9\nmov rdx 9\nleave").unwrap();
+        assert!(interpreter.to_string().contains("rcx: ") && !interpreter.to_string().contains("rdx: "));
+
+        interpreter.run().unwrap();
+        assert_eq!(Register::ALL.map(|register| interpreter.memory.register(register)), [1, 2, 3, 4].map(Type::Integer));
+        assert!(interpreter.to_string().contains("rdx: "));
+    }
+
+    #[test]
+    fn floats_in_exponent_notation_round_trip() {
+        let interpreter = Interpreter::from_str("mov rax 100000000000000000.0\nmov rbx 2.5e-2\nret rax").unwrap();
+        assert_eq!(interpreter.source_code[0].to_string(), "mov rax 1e17");
+        interpreter.verify_roundtrip().unwrap();
+    }
+
+    #[test]
+    fn spawned_child_reads_from_the_same_input() {
+        let mut interpreter = Interpreter::from_str("syscall read\nmov rbx rax\nmov rax \"child\"\nsyscall spawn\nsyscall read\nret rax").unwrap()
+            .with_input(Rc::new(RefCell::new(std::io::Cursor::new("parent\nchild\nparent again\n"))));
+        interpreter.register_program("child", "syscall read\nret 0").unwrap();
+
+        assert_eq!(interpreter.run().unwrap(), Type::String("parent again".to_string()));
+        assert_eq!(interpreter.memory.register(Register::Rbx), Type::String("parent".to_string()));
+    }
+}
@@ -4,35 +4,54 @@ use crate::command::Command;
 use crate::interpreter::{Interpreter, SemanticError};
 use crate::program_error::{ParseError, ProgramError};
 
-#[derive(Debug, Clone)]
+/// Implicit label after the last command. Jumping to it terminates the program
+pub const END_LABEL: &str = "__end";
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum JumpDestination {
     /// Name of the label, index in the commands vector
     Label(String)
 }
 
 impl JumpDestination {
+    /// Returns the index of the command the destination refers to
+    pub fn resolve(&self, interpreter: &Interpreter) -> Result<usize, ProgramError> {
+        let JumpDestination::Label(target_label) = self;
+
+        match interpreter.source_code.iter().position(|a| matches!(a, Command::Label(source_label, _) if *source_label == *target_label)) {
+            Some(index) => Ok(index),
+            None if target_label == END_LABEL => Ok(interpreter.source_code.len()),
+            None => Err(ProgramError::LabelNotFound(target_label.to_string()))
+        }
+    }
+
     /// Checks if the label has the provided command in all code_paths
     pub fn ends_with(&self, interpreter: &Interpreter, last_command: fn(&Command) -> bool, error: fn(&String) -> SemanticError) -> Result<(), ProgramError> {
-        let JumpDestination::Label(target_label) = self;
+        // the label itself is skipped
+        let mut index = self.resolve(interpreter)? + 1;
 
-        let possible_index = interpreter.source_code.iter().position(|a|
-            matches!(a, Command::Label(source_label) if *source_label == *target_label)
-        );
-
-        if let Some(mut index) = possible_index {
-            while let Some(inner_labels_command) = interpreter.source_code.get(index) {
-                match inner_labels_command {
-                    Command::Label(label) if *label != *target_label  => return Err(error(target_label).into()),
-                    potential_last_command if last_command(potential_last_command) => return Ok(()),
-                    _ => index += 1
-                }
+        while let Some(inner_labels_command) = interpreter.source_code.get(index) {
+            match inner_labels_command {
+                Command::Label(_, _) => return Err(error(&self.to_string()).into()),
+                Command::Jmp(JumpDestination::Label(label)) if label == END_LABEL => return Ok(()),
+                potential_last_command if last_command(potential_last_command) => return Ok(()),
+                _ => index += 1
             }
-        } else {
-            return Err(ProgramError::LabelNotFound(target_label.to_string()));
         }
 
         Ok(())
     }
+
+    /// Checks if the label contains the provided command before the next label starts
+    pub fn contains(&self, interpreter: &Interpreter, command: fn(&Command) -> bool) -> Result<bool, ProgramError> {
+        let index = self.resolve(interpreter)?;
+
+        Ok(interpreter.source_code.iter()
+            .skip(index + 1)
+            .take_while(|inner_labels_command| !matches!(inner_labels_command, Command::Label(_, _)))
+            .any(command))
+    }
 }
 
 impl Display for JumpDestination {
@@ -49,4 +68,38 @@ impl FromStr for JumpDestination {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         Ok(JumpDestination::Label(s.to_string()))
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::assignment::Type;
+    use crate::test_utils::{captured, text};
+    use super::*;
+
+    #[test]
+    fn jumping_to_the_end_halts() {
+        let (mut interpreter, output) = captured("jmp __end\nmov rax \"unreachable\"\nsyscall prints\nret 3");
+
+        assert_eq!(interpreter.run().unwrap(), Type::Integer(0));
+        assert_eq!(text(&output), "");
+    }
+
+    #[test]
+    fn semantic_check_accepts_a_path_ending_at_the_end() {
+        let mut interpreter = Interpreter::from_str("call f\nret 1\nf:\nmov rax 1\njmp __end").unwrap();
+        interpreter.semantic_check().unwrap();
+        assert_eq!(interpreter.run().unwrap(), Type::Integer(0));
+
+        let interpreter = Interpreter::from_str("mov rax 1\nret 1\nf:\nmov rax 1\nother:\nleave").unwrap();
+        assert_eq!(JumpDestination::Label(END_LABEL.to_string()).resolve(&interpreter).unwrap(), interpreter.source_code.len());
+    }
+
+    #[test]
+    fn resolve_finds_the_label_index() {
+        let interpreter = Interpreter::from_str("mov rax 1\nret rax\nf:\nleave").unwrap();
+
+        assert_eq!(JumpDestination::Label("f".to_string()).resolve(&interpreter).unwrap(), 2);
+        assert_eq!(JumpDestination::Label(END_LABEL.to_string()).resolve(&interpreter).unwrap(), 4);
+        assert!(matches!(JumpDestination::Label("g".to_string()).resolve(&interpreter), Err(ProgramError::LabelNotFound(_))));
+    }
+}
@@ -0,0 +1,13 @@
+pub mod command;
+pub mod assignment;
+pub mod address;
+pub mod register;
+pub mod jump;
+pub mod interpreter;
+pub mod memory;
+pub mod program_error;
+pub mod output;
+pub mod input;
+
+#[cfg(test)]
+mod test_utils;
@@ -1,17 +1,90 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
+use std::rc::Rc;
 use thiserror::Error;
 use crate::assignment::{Assignment, OperationError, Type};
-use crate::address::{Address};
-use crate::interpreter::{RegisterMemory, StackFrame};
+use crate::address::{Address, Destination, TryAdd};
+use crate::interpreter::{RegisterMemory, StackFrame, StateDelta};
+use crate::input::Input;
+use crate::output::Output;
 use crate::register::Register;
 
-#[derive(Debug)]
+/// Maximum amount of slots the heap can hold, so a huge allocation fails instead of aborting the interpreter
+pub const HEAP_LIMIT: usize = 1 << 20;
+
+/// Maximum length of an array created with `arrnew`
+pub const ARRAY_LIMIT: usize = 1 << 20;
+
+/// Maximum length in bytes of a string built with `mul` or `append`
+pub const STRING_LIMIT: usize = 1 << 20;
+
+#[derive(Debug, Clone)]
 pub struct Memory {
-    pub rax: Type,
-    pub rbx: Type,
-    pub rcx: Type,
+    /// Values of the registers. Registers, which aren't in the map, are untyped
+    pub registers: HashMap<Register, Type>,
+    pub flags: Flags,
+    /// If enabled, arithmetic instructions like `add` and `sub` update the flags with their integer result, like x86 does.
+    /// Conditional jumps without an operand (`je label`) can then branch directly on the result
+    /// of the last arithmetic operation, without a separate `cmp`.
+    ///
+    /// Disabled by default, because it's an additional side effect of every arithmetic instruction.
+    pub flag_arithmetic: bool,
+    /// If enabled, `printf` fails, if the amount of placeholders in the format string doesn't match
+    /// the amount of arguments, instead of ignoring the arguments or the placeholders
+    pub strict_printf: bool,
+    /// If enabled, `sete`, `setne`, `setl`, `setg` and `streq` store a Boolean instead of 1 or 0
+    pub boolean_comparisons: bool,
     pub stack_frame: Vec<StackFrame>,
-    pub stack: Vec<Type>
+    pub output: Output,
+    pub input: Input,
+    /// The stack is shared between snapshots and only copied on the first write after a snapshot
+    pub stack: Rc<Vec<Type>>,
+    /// Index of the slot `push` wrote last and `pop` reads next. The stack grows down, so it's the length of the stack while nothing is pushed
+    pub stack_pointer: usize,
+    pub heap: Vec<Type>,
+    /// Base and size of every region on the heap, which is currently allocated
+    pub heap_allocations: Vec<(usize, usize)>,
+    /// Read-only strings by their symbol, defined with `.string`
+    pub strings: HashMap<String, String>,
+    /// Simulated time in milliseconds. Only `syscall sleep` advances it, so programs are deterministic.
+    /// It's never negative and fails to advance past `isize::MAX`, so `syscall time` can return it as an integer
+    pub clock: isize,
+}
+
+/// Status flags, read by conditional jumps which don't receive an explicit operand, `set*` and `cmov*`.
+///
+/// Only `cmp` updates the flags, as well as arithmetic instructions if `flag_arithmetic` is enabled. `clrflags`
+/// clears them, every other instruction leaves them untouched. While the flags are cleared, which they are at the
+/// start of the program as well, conditional jumps don't branch, `set*` stores 0 and `cmov*` doesn't move
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Flags {
+    /// How the last result compares to zero, `None` while the flags are cleared
+    pub ordering: Option<Ordering>,
+}
+
+impl Flags {
+    /// Updates the flags based on the result. Results, which are not integers, leave the flags untouched
+    pub fn update(&mut self, result: &Type) {
+        if let Type::Integer(value) = result {
+            self.ordering = Some(value.cmp(&0));
+        }
+    }
+
+    /// The last result was zero
+    pub fn zero(&self) -> bool {
+        self.ordering == Some(Ordering::Equal)
+    }
+
+    /// The last result was negative
+    pub fn sign(&self) -> bool {
+        self.ordering == Some(Ordering::Less)
+    }
+
+    /// Returns the flags in the same representation `cmp` uses: -1, 0 or 1. `None`, while the flags are cleared
+    pub fn sign_value(&self) -> Option<isize> {
+        self.ordering.map(|ordering| ordering as isize)
+    }
 }
 
 #[derive(Error, Debug)]
@@ -19,7 +92,20 @@ pub enum MemoryError {
     Write(Address),
     Read(Assignment),
     SegmentationFault(String),
-    OperationError(#[from] OperationError)
+    OperationError(#[from] OperationError),
+    Output(#[from] std::io::Error),
+    Input(std::io::Error),
+    UnknownSyscall(String),
+    UninitializedPointer(Destination),
+    InvalidFree(Address),
+    IndexOutOfBounds { index: isize, length: usize },
+    UnknownSymbol(String),
+    FormatMismatch { placeholders: usize, arguments: usize },
+    /// An operation failed, the assignments are the operands as written in the source code
+    OperandError { error: OperationError, operands: Vec<Assignment> },
+    ReadOnly(Address),
+    OutOfMemory { requested: usize, available: usize },
+    ClockOverflow { clock: isize, milliseconds: isize },
 }
 
 impl Display for MemoryError {
@@ -28,22 +114,41 @@ impl Display for MemoryError {
             MemoryError::Write(d) => format!("Cannot write at: {d}"),
             MemoryError::Read(a) => format!("Cannot not read at: {a}"),
             MemoryError::OperationError(o) => format!("Cannot operate: {o}"),
-            MemoryError::SegmentationFault(fault_message) => format!("Segentation fault: {fault_message}")
+            MemoryError::SegmentationFault(fault_message) => format!("Segmentation fault: {fault_message}"),
+            MemoryError::Output(e) => format!("Cannot write output: {e}"),
+            MemoryError::Input(e) => format!("Cannot read input: {e}"),
+            MemoryError::UnknownSyscall(name) => format!("Unknown syscall: {name}"),
+            MemoryError::UninitializedPointer(d) => format!("Cannot dereference [{d}], the pointer is uninitialized"),
+            MemoryError::InvalidFree(a) => format!("Cannot free {a}, it's not allocated or was already freed"),
+            MemoryError::IndexOutOfBounds { index, length } => format!("Index {index} is out of bounds for length {length}"),
+            MemoryError::OperandError { error, operands } => {
+                let operands = operands.iter().map(|a| a.disassemble()).collect::<Vec<_>>().join(", ");
+                format!("Cannot operate on ({operands}): {error}")
+            },
+            MemoryError::FormatMismatch { placeholders, arguments } => format!("The format string has {placeholders} placeholders, but {arguments} arguments are provided"),
+            MemoryError::UnknownSymbol(name) => format!("Unknown symbol: {name}, define it with .string {name} \"...\""),
+            MemoryError::ReadOnly(a) => format!("Cannot write at: {a}, it's read-only"),
+            MemoryError::OutOfMemory { requested, available } => format!("Cannot allocate {requested} slots, only {available} are available"),
+            MemoryError::ClockOverflow { clock, milliseconds } => format!("Cannot sleep {milliseconds} ms, the clock at {clock} ms would overflow"),
         })
     }
 }
 
+fn invalid_memory_index(address: &Address) -> MemoryError {
+    MemoryError::SegmentationFault(format!("A {}-typed address ({}) cannot be used as a memory index", address.kind(), address.disassemble()))
+}
+
+/// A slot in memory, a pointer can point to
+enum Slot {
+    Stack(usize),
+    Heap(usize),
+}
+
 impl Memory {
     pub fn get(&self, assignment: &Assignment) -> Result<Type, MemoryError> {
         match assignment {
             Assignment::Value(value) => Ok(value.clone()),
-            Assignment::Address(Address::Register(register)) => {
-                match register {
-                    Register::Rax => Ok(self.rax.clone()),
-                    Register::Rbx => Ok(self.rbx.clone()),
-                    Register::Rcx => Ok(self.rcx.clone())
-                }
-            }
+            Assignment::Address(Address::Register(register)) => Ok(self.register(*register)),
             Assignment::Address(Address::StackPointer(index)) => {
                 if *index >= self.stack.len() {
                     return Err(MemoryError::Read(Assignment::Address(Address::StackPointer(*index))));
@@ -51,64 +156,325 @@ impl Memory {
 
                 Ok(self.stack[*index].clone())
             },
+            Assignment::Address(Address::HeapPointer(index)) => {
+                Ok(self.heap[self.heap_index(*index)?].clone())
+            },
             Assignment::Address(Address::Reference(reference)) => {
-                let a = Assignment::from(reference.clone());
-
-                Ok(self.get(&a)?)
+                match self.dereference(reference)? {
+                    Slot::Stack(index) => self.get(&Assignment::Address(Address::StackPointer(index))),
+                    Slot::Heap(index) => self.get(&Assignment::Address(Address::HeapPointer(index))),
+                }
+            },
+            Assignment::Address(Address::ProgramCounter) => Err(MemoryError::Read(assignment.clone())),
+            Assignment::Address(Address::Symbol(name)) => {
+                self.strings.get(name).map(|value| Type::String(value.clone())).ok_or_else(|| MemoryError::UnknownSymbol(name.to_string()))
             }
         }
     }
 
     pub fn set(&mut self, destination: &Address, value: Type) -> Result<(), MemoryError> {
-        fn usize_from(memory: &Memory, ty: &Type) -> Result<usize, MemoryError> {
-            match ty {
-                Type::Integer(integer_value) => {
-                    if *integer_value <= 0 || *integer_value as usize >= memory.stack.len() {
-                        return Err(MemoryError::Read(Assignment::Value(Type::Integer(*integer_value))));
-                    }
-
-                    Ok(*integer_value as usize)
+        match destination {
+            Address::Register(register) => self.set_register(*register, value),
+            Address::StackPointer(index) => {
+                if *index >= self.stack.len() {
+                    return Err(MemoryError::Write(destination.clone()));
                 }
-                Type::Address(a) => {
-                    match a {
-                        Address::StackPointer(i) => Ok(*i),
-                        Address::Register(_) => Err(MemoryError::SegmentationFault("Cannot read a registers position".to_string())),
-                        Address::Reference(_) => Err(MemoryError::SegmentationFault("Only single pointers supported".to_string()))
-                    }
+
+                Rc::make_mut(&mut self.stack)[*index] = value;
+            },
+            Address::HeapPointer(index) => {
+                let index = self.heap_index(*index)?;
+                self.heap[index] = value;
+            },
+            Address::Reference(reference) => {
+                match self.dereference(reference)? {
+                    Slot::Stack(index) => self.set(&Address::StackPointer(index), value)?,
+                    Slot::Heap(index) => self.set(&Address::HeapPointer(index), value)?,
                 }
-                Type::String(t) => Err(MemoryError::Read(Assignment::Value(Type::String(t.to_string())))),
-                Type::Untyped => Err(MemoryError::Read(Assignment::Value(Type::Untyped)))
-            }
+            },
+            // the program counter belongs to the interpreter, which handles `mov pc`
+            Address::ProgramCounter => return Err(MemoryError::Write(destination.clone())),
+            Address::Symbol(_) => return Err(MemoryError::ReadOnly(destination.clone()))
         }
 
+        Ok(())
+    }
+
+    /// Returns the value at the address for modifying it in place
+    pub fn get_mut(&mut self, destination: &Address) -> Result<&mut Type, MemoryError> {
         match destination {
-            Address::Register(register) => {
-                match register {
-                    Register::Rax => self.rax = value,
-                    Register::Rbx => self.rbx = value,
-                    Register::Rcx => self.rcx = value
-                }
-            }
+            Address::Register(register) => Ok(self.registers.entry(*register).or_insert(Type::Untyped)),
             Address::StackPointer(index) => {
                 if *index >= self.stack.len() {
                     return Err(MemoryError::Write(destination.clone()));
                 }
 
-                self.stack[*index] = value;
+                Ok(&mut Rc::make_mut(&mut self.stack)[*index])
+            },
+            Address::HeapPointer(index) => {
+                let index = self.heap_index(*index)?;
+                Ok(&mut self.heap[index])
+            },
+            Address::Reference(reference) => {
+                match self.dereference(reference)? {
+                    Slot::Stack(index) => self.get_mut(&Address::StackPointer(index)),
+                    Slot::Heap(index) => self.get_mut(&Address::HeapPointer(index)),
+                }
+            },
+            Address::ProgramCounter => Err(MemoryError::Write(destination.clone())),
+            Address::Symbol(_) => Err(MemoryError::ReadOnly(destination.clone()))
+        }
+    }
+
+    /// Returns the inline arguments of a syscall. Without inline arguments, rax and rbx are the arguments
+    pub fn syscall_arguments(&self, inline_arguments: &[Assignment]) -> Result<Vec<Type>, MemoryError> {
+        if inline_arguments.is_empty() {
+            return Ok(vec![self.register(Register::Rax), self.register(Register::Rbx)]);
+        }
+
+        inline_arguments.iter().map(|assignment| self.get(assignment)).collect()
+    }
+
+    /// Reads the element at the index of the array starting at the base address. On the stack, the array
+    /// reaches until the end of the stack, on the heap until the end of the allocated region
+    pub fn load_indexed(&self, base: &Type, index: &Type) -> Result<Type, MemoryError> {
+        let Type::Integer(index) = index else {
+            return Err(OperationError::WrongType { expected: "Integer".to_string(), actual: format!("{index}") }.into());
+        };
+
+        let (base, length) = match base {
+            Type::Address(Address::StackPointer(base)) => (Address::StackPointer(*base), self.stack.len().saturating_sub(*base)),
+            Type::Address(Address::HeapPointer(base)) => {
+                let length = self.heap_allocations.iter()
+                    .find(|(allocated_base, size)| (*allocated_base..*allocated_base + *size).contains(base))
+                    .map_or(0, |(allocated_base, size)| allocated_base + size - base);
+
+                (Address::HeapPointer(*base), length)
             },
-            Address::Reference(destination) => {
-                let a = Assignment::from(destination.clone());
-                let ty = &self.get(&a)?;
-                let address = usize_from(self, ty)?;
+            rest => return Err(OperationError::WrongType { expected: "stack or heap Address".to_string(), actual: format!("{rest}") }.into())
+        };
 
-                self.stack[address] = value;
+        if *index < 0 || *index as usize >= length {
+            return Err(MemoryError::IndexOutOfBounds { index: *index, length });
+        }
+
+        self.get(&Assignment::Address(base.try_add(index).map_err(OperationError::from)?))
+    }
+
+    /// Resolves the slot the pointer stored in the reference points to
+    fn dereference(&self, reference: &Destination) -> Result<Slot, MemoryError> {
+        match self.get(&Assignment::from(reference.clone()))? {
+            Type::Integer(integer_value) => {
+                if integer_value < 0 || integer_value as usize >= self.stack.len() {
+                    return Err(MemoryError::Read(Assignment::Value(Type::Integer(integer_value))));
+                }
+
+                Ok(Slot::Stack(integer_value as usize))
+            }
+            Type::Address(a) => {
+                match &a {
+                    Address::StackPointer(i) => Ok(Slot::Stack(*i)),
+                    Address::HeapPointer(i) => Ok(Slot::Heap(*i)),
+                    // only single pointers are supported
+                    Address::Register(_) | Address::Reference(_) | Address::ProgramCounter | Address::Symbol(_) => Err(invalid_memory_index(&a)),
+                }
             }
+            Type::Untyped => Err(MemoryError::UninitializedPointer(reference.clone())),
+            rest => Err(MemoryError::Read(Assignment::Value(rest)))
         }
+    }
+
+    /// Checks, if the index lies within an allocated region of the heap
+    fn heap_index(&self, index: usize) -> Result<usize, MemoryError> {
+        if self.heap_allocations.iter().any(|(base, size)| (*base..*base + *size).contains(&index)) {
+            Ok(index)
+        } else {
+            Err(MemoryError::SegmentationFault(format!("{} is not allocated", Address::HeapPointer(index))))
+        }
+    }
+
+    /// Allocates an untyped region of the size on the heap and returns its base address. Freed regions
+    /// aren't reused, so the heap fails to allocate once `HEAP_LIMIT` slots were allocated in total
+    pub fn allocate(&mut self, size: usize) -> Result<Address, MemoryError> {
+        let base = self.heap.len();
+        let available = HEAP_LIMIT - base;
+        if size > available {
+            return Err(MemoryError::OutOfMemory { requested: size, available });
+        }
+
+        self.heap.extend(std::iter::repeat_n(Type::Untyped, size));
+        self.heap_allocations.push((base, size));
+
+        Ok(Address::HeapPointer(base))
+    }
+
+    /// Releases the region allocated at the base address
+    pub fn free(&mut self, base: usize) -> Result<(), MemoryError> {
+        let Some(position) = self.heap_allocations.iter().position(|(allocated_base, _)| *allocated_base == base) else {
+            return Err(MemoryError::InvalidFree(Address::HeapPointer(base)));
+        };
+
+        let (base, size) = self.heap_allocations.remove(position);
+        self.heap[base..base + size].fill(Type::Untyped);
 
         Ok(())
     }
 
+    /// Creates a copy of the memory. The stack isn't copied until one of both memories writes to it
+    pub fn snapshot(&self) -> Memory {
+        self.clone()
+    }
+
+    /// Lists the registers, stack and heap slots, which differ between this memory and the other one, e.g. a
+    /// snapshot and the current state. Slots only one of both has are compared against an untyped value
+    pub fn diff(&self, other: &Memory) -> Vec<StateDelta> {
+        fn slots(before: &[Type], after: &[Type], address: fn(usize) -> Address) -> Vec<StateDelta> {
+            (0..before.len().max(after.len()))
+                .map(|index| (index, before.get(index).unwrap_or(&Type::Untyped), after.get(index).unwrap_or(&Type::Untyped)))
+                .filter(|(_, before, after)| before != after)
+                .map(|(index, before, after)| StateDelta { address: address(index), before: before.clone(), after: after.clone() })
+                .collect()
+        }
+
+        let mut deltas = Register::ALL.into_iter()
+            .map(|register| (register, self.register(register), other.register(register)))
+            .filter(|(_, before, after)| before != after)
+            .map(|(register, before, after)| StateDelta { address: Address::Register(register), before, after })
+            .collect::<Vec<_>>();
+
+        deltas.extend(slots(&self.stack, &other.stack, Address::StackPointer));
+        deltas.extend(slots(&self.heap, &other.heap, Address::HeapPointer));
+        deltas
+    }
+
+    /// Returns the outcome of a comparison as a Boolean, if `boolean_comparisons` is enabled, as 1 or 0 otherwise
+    pub fn comparison_result(&self, value: bool) -> Type {
+        if self.boolean_comparisons {
+            Type::Boolean(value)
+        } else {
+            Type::Integer(isize::from(value))
+        }
+    }
+
     pub fn register_state(&self) -> RegisterMemory {
-        (self.rax.clone(), self.rbx.clone(), self.rcx.clone())
+        self.registers.clone()
+    }
+
+    pub fn restore_register_state(&mut self, register_state: RegisterMemory) {
+        self.registers = register_state;
+    }
+
+    pub fn register(&self, register: Register) -> Type {
+        self.registers.get(&register).cloned().unwrap_or(Type::Untyped)
+    }
+
+    pub fn set_register(&mut self, register: Register, value: Type) {
+        self.registers.insert(register, value);
+    }
+}
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use crate::interpreter::Interpreter;
+    use crate::program_error::ProgramError;
+    use super::*;
+
+    #[test]
+    fn snapshot_shares_the_stack_until_a_write() {
+        let mut memory = Interpreter::from_str("ret 0").unwrap().memory;
+        memory.stack = Rc::new(vec![Type::Integer(1); 1_000_000]);
+
+        let snapshot = memory.snapshot();
+        assert!(Rc::ptr_eq(&snapshot.stack, &memory.stack));
+        assert_eq!(memory.get(&Assignment::Address(Address::StackPointer(3))).unwrap(), Type::Integer(1));
+        assert!(Rc::ptr_eq(&snapshot.stack, &memory.stack));
+
+        memory.set(&Address::StackPointer(3), Type::Integer(9)).unwrap();
+        assert!(!Rc::ptr_eq(&snapshot.stack, &memory.stack));
+        assert_eq!(snapshot.stack[3], Type::Integer(1));
+        assert_eq!(memory.stack[3], Type::Integer(9));
+    }
+
+    #[test]
+    fn immediates_are_written_to_the_stack_and_through_references() {
+        let mut interpreter = Interpreter::from_str("mov sp[3] 5\nlea rax sp[4]\nmov [rax] 7\nmov rbx 0\nmov [rbx] 9\nret 0").unwrap();
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.memory.stack[3], Type::Integer(5));
+        assert_eq!(interpreter.memory.stack[4], Type::Integer(7));
+        assert_eq!(interpreter.memory.stack[0], Type::Integer(9));
+    }
+
+    #[test]
+    fn writing_through_an_uninitialized_pointer_says_so() {
+        let mut interpreter = Interpreter::from_str("mov [rax] 5\nret 0").unwrap();
+
+        assert_eq!(interpreter.run().unwrap_err().to_string(), "at instruction 0 (line 1): Cannot dereference [rax], the pointer is uninitialized");
+    }
+
+    #[test]
+    fn heap_regions_are_allocated_written_read_and_freed() {
+        let mut interpreter = Interpreter::from_str("mov rax 3\nsyscall malloc\nmov rbx rax\nmov [rbx] 5\nadd rcx rbx 2\nmov [rcx] \"x\"\nmov sp[0] [rcx]\nmov sp[1] hp[0]\nsyscall free\nret 0").unwrap();
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.memory.stack[0], Type::String("x".to_string()));
+        assert_eq!(interpreter.memory.stack[1], Type::Integer(5));
+        assert!(interpreter.memory.heap_allocations.is_empty());
+    }
+
+    #[test]
+    fn freeing_twice_fails() {
+        let mut interpreter = Interpreter::from_str("mov rax 3\nsyscall malloc\nsyscall free\nsyscall free\nret 0").unwrap();
+
+        match interpreter.run() {
+            Err(ProgramError::Runtime { instruction: 3, error, .. }) => assert!(matches!(*error, ProgramError::Memory(MemoryError::InvalidFree(Address::HeapPointer(0))))),
+            other => panic!("expected an invalid free, got {other:?}")
+        }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn huge_allocation_fails() {
+        let mut interpreter = Interpreter::from_str("mov rax 9223372036854775807\nsyscall malloc\nret 0").unwrap();
+
+        match interpreter.run() {
+            Err(ProgramError::Runtime { error, .. }) => assert!(matches!(*error, ProgramError::Memory(MemoryError::OutOfMemory { available: HEAP_LIMIT, .. }))),
+            other => panic!("expected an allocation failure, got {other:?}")
+        }
+    }
+
+    #[test]
+    fn loadidx_reads_within_the_region() {
+        let mut interpreter = Interpreter::from_str("mov sp[5] 42\nlea rbx sp[3]\nloadidx rax rbx 2\nret rax").unwrap();
+        assert_eq!(interpreter.run().unwrap(), Type::Integer(42));
+    }
+
+    #[test]
+    fn loadidx_out_of_range_reports_the_length() {
+        let mut interpreter = Interpreter::from_str("lea rbx sp[60]\nloadidx rax rbx 4\nret rax").unwrap();
+        assert_eq!(interpreter.run().unwrap_err().to_string(), "at instruction 1 (line 2): Index 4 is out of bounds for length 4");
+
+        let mut interpreter = Interpreter::from_str("mov rax 3\nsyscall malloc\nloadidx rbx rax 3\nret rbx").unwrap();
+        assert_eq!(interpreter.run().unwrap_err().to_string(), "at instruction 2 (line 3): Index 3 is out of bounds for length 3");
+    }
+
+    #[test]
+    fn invalid_memory_indices_name_the_address_kind() {
+        let cases = [
+            ("lea rax rbx", "A register-typed address (rbx) cannot be used as a memory index"),
+            ("lea rax [rbx]", "A reference-typed address ([rbx]) cannot be used as a memory index"),
+            ("lea rax pc", "A program counter-typed address (pc) cannot be used as a memory index"),
+            (".string msg \"hi\"\nlea rax msg", "A symbol-typed address (msg) cannot be used as a memory index"),
+        ];
+
+        for (setup, message) in cases {
+            let mut interpreter = Interpreter::from_str(&format!("{setup}\nmov [rax] 1\nret 0")).unwrap();
+            match interpreter.run() {
+                Err(ProgramError::Runtime { error, .. }) => match *error {
+                    ProgramError::Memory(MemoryError::SegmentationFault(fault_message)) => assert_eq!(fault_message, message),
+                    other => panic!("expected a segmentation fault, got {other:?}")
+                },
+                other => panic!("expected a segmentation fault, got {other:?}")
+            }
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use std::cell::RefCell;
+use std::fmt::{Debug, Formatter};
+use std::io::{BufWriter, Write};
+use std::rc::Rc;
+
+/// The writer everything printed by the program ends up in. Clones share the same writer.
+///
+/// Output is buffered and only guaranteed to be written after `flush`, which the interpreter
+/// calls as soon as the program halts
+#[derive(Clone)]
+pub struct Output {
+    writer: Rc<RefCell<BufWriter<SharedWriter>>>,
+}
+
+/// Allows the caller to keep a handle to the writer, e.g. to inspect captured output
+struct SharedWriter(Rc<RefCell<dyn Write>>);
+
+impl Write for SharedWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+impl Output {
+    pub fn new(writer: Rc<RefCell<dyn Write>>) -> Self {
+        Self { writer: Rc::new(RefCell::new(BufWriter::new(SharedWriter(writer)))) }
+    }
+
+    pub fn println(&self, line: &str) -> std::io::Result<()> {
+        writeln!(self.writer.borrow_mut(), "{line}")
+    }
+
+    pub fn flush(&self) -> std::io::Result<()> {
+        self.writer.borrow_mut().flush()
+    }
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Self::new(Rc::new(RefCell::new(std::io::stdout())))
+    }
+}
+
+impl Debug for Output {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Output")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_are_buffered_until_flush() {
+        let captured = Rc::new(RefCell::new(Vec::<u8>::new()));
+        let output = Output::new(captured.clone());
+
+        output.println("buffered").unwrap();
+        assert!(captured.borrow().is_empty());
+
+        output.flush().unwrap();
+        assert_eq!(captured.borrow().as_slice(), b"buffered\n");
+    }
+}
@@ -1,6 +1,7 @@
 use std::fmt::{Display, Formatter};
 use std::num::ParseIntError;
 use thiserror::Error;
+use crate::address::Address;
 use crate::interpreter::SemanticError;
 use crate::memory::MemoryError;
 
@@ -37,6 +38,22 @@ pub enum ProgramError {
     Memory(#[from] MemoryError),
     Semantic(#[from] SemanticError),
     LabelNotFound(String),
+    RoundTripMismatch(usize, String),
+    ProgramCounterOutOfBounds(isize),
+    ProgramNotFound(String),
+    SpawnDepthExceeded(usize),
+    StepLimitExceeded(usize),
+    ReturnCountMismatch { expected: usize, actual: usize },
+    /// A call expecting return values into the addresses was left with `leave`
+    LeaveWithoutValue(Vec<Address>),
+    UnwindOutOfBounds { frames: usize, depth: usize },
+    /// The program halted in strict frame mode, while the stack frames with these return addresses were still open
+    LeakedStackFrames(Vec<usize>),
+    /// No context was saved with `savectx` under the name
+    ContextNotFound(String),
+    /// Executing the next instruction would use more gas than the limit allows
+    OutOfGas { limit: u64, used: u64 },
+    Runtime { instruction: usize, line: Option<usize>, error: Box<ProgramError> },
 }
 
 impl Display for ProgramError {
@@ -45,7 +62,26 @@ impl Display for ProgramError {
             ProgramError::Parse(p) => format!("{p}"),
             ProgramError::Memory(m) => format!("{m}"),
             ProgramError::LabelNotFound(jump_destination) => format!("Cannot find jmp destination {jump_destination}"),
-            ProgramError::Semantic(s) => format!("{s}")
+            ProgramError::Semantic(s) => format!("{s}"),
+            ProgramError::RoundTripMismatch(index, disassembly) => format!("Instruction {index} does not survive a round trip through its disassembly '{disassembly}'"),
+            ProgramError::ProgramCounterOutOfBounds(index) => format!("Cannot set the program counter to {index}, there is no instruction at this index"),
+            ProgramError::ProgramNotFound(name) => format!("Cannot spawn '{name}', no program is registered with this name"),
+            ProgramError::SpawnDepthExceeded(depth) => format!("Cannot spawn more than {depth} nested programs"),
+            ProgramError::StepLimitExceeded(max_steps) => format!("The program didn't halt within {max_steps} steps"),
+            ProgramError::ReturnCountMismatch { expected, actual } => format!("Expected {expected} return values, but {actual} were returned"),
+            ProgramError::LeaveWithoutValue(destinations) => {
+                let destinations = destinations.iter().map(|a| a.disassemble()).collect::<Vec<_>>().join(",");
+                format!("The call returning into {destinations} expects a value, but was left with `leave` instead of `ret`")
+            },
+            ProgramError::UnwindOutOfBounds { frames, depth } => format!("Cannot unwind {frames} stack frames, only {depth} exist"),
+            ProgramError::LeakedStackFrames(return_addresses) => {
+                let return_addresses = return_addresses.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(", ");
+                format!("The program halted with open stack frames returning to: {return_addresses}")
+            },
+            ProgramError::ContextNotFound(name) => format!("Cannot load the context '{name}', it was never saved"),
+            ProgramError::OutOfGas { limit, used } => format!("Out of gas: {used} of {limit} used, the next instruction costs more than what's left"),
+            ProgramError::Runtime { instruction, line: Some(line), error } => format!("at instruction {instruction} (line {line}): {error}"),
+            ProgramError::Runtime { instruction, line: None, error } => format!("at instruction {instruction}: {error}"),
         })
     }
 }
\ No newline at end of file
@@ -1,10 +1,17 @@
 use std::fmt::{Display, Formatter};
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Register {
     Rax,
     Rbx,
     Rcx,
+    Rdx,
+}
+
+impl Register {
+    /// Every register in the order they are printed
+    pub const ALL: [Register; 4] = [Register::Rax, Register::Rbx, Register::Rcx, Register::Rdx];
 }
 
 impl Display for Register {
@@ -12,7 +19,8 @@ impl Display for Register {
         write!(f, "{}", match self {
             Register::Rax => "rax",
             Register::Rbx => "rbx",
-            Register::Rcx => "rcx"
+            Register::Rcx => "rcx",
+            Register::Rdx => "rdx"
         })
     }
 }
\ No newline at end of file
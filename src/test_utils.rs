@@ -0,0 +1,24 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::str::FromStr;
+use crate::assignment::Type;
+use crate::interpreter::Interpreter;
+use crate::program_error::ProgramError;
+
+/// Parses and runs the program, panicking on parse errors
+pub fn run(source: &str) -> Result<Type, ProgramError> {
+    Interpreter::from_str(source).unwrap().run()
+}
+
+/// Parses the program with an output, which can be inspected after running it
+pub fn captured(source: &str) -> (Interpreter, Rc<RefCell<Vec<u8>>>) {
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let interpreter = Interpreter::from_str(source).unwrap().with_output(output.clone());
+
+    (interpreter, output)
+}
+
+/// Everything written into the captured output so far
+pub fn text(output: &Rc<RefCell<Vec<u8>>>) -> String {
+    String::from_utf8(output.borrow().clone()).unwrap()
+}